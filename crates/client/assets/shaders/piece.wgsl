@@ -10,13 +10,29 @@ var mask_texture: texture_2d<f32>;
 @group(1) @binding(3)
 var mask_sampler: sampler;
 
+@group(1) @binding(4)
+var back_texture: texture_2d<f32>;
+
+@group(1) @binding(5)
+var back_sampler: sampler;
+
+struct PieceFace {
+    flipped: f32,
+    _padding: vec3<f32>,
+};
+
+@group(1) @binding(6)
+var<uniform> face: PieceFace;
+
 @fragment
 fn fragment(
     #import bevy_pbr::mesh_vertex_output
 ) -> @location(0) vec4<f32> {
     // uv_rect is encoded in vertex color: xy = offset, zw = scale
     let image_uv = uv * color.zw + color.xy;
-    let col = textureSample(puzzle_texture, puzzle_sampler, image_uv);
+    let front = textureSample(puzzle_texture, puzzle_sampler, image_uv);
+    let back = textureSample(back_texture, back_sampler, image_uv);
+    let col = select(front, back, face.flipped > 0.5);
     let mask = textureSample(mask_texture, mask_sampler, uv);
     return vec4(col.rgb, col.a * mask.a);
 }
@@ -1,6 +1,7 @@
 use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
 
 use game::Puzzle;
+use image::{Rgba, RgbaImage};
 
 use crate::{
     colors::{LIGHT, MED},
@@ -33,11 +34,37 @@ fn spawn_board(
     mut commands: Commands,
     puzzle: Res<Puzzle>,
     mut meshes: ResMut<Assets<Mesh>>,
+    mut image_assets: ResMut<Assets<Image>>,
     board_material: Res<BoardMaterial>,
     board_query: Query<Entity, With<Board>>,
 ) {
     despawn(board_query, &mut commands);
 
+    if let Some(outline) = puzzle.outline_mask() {
+        // shaped puzzles get a board in the shape of their outline instead of a rectangle
+        let board_image = RgbaImage::from_fn(outline.width(), outline.height(), |x, y| {
+            Rgba([255, 255, 255, outline.get_pixel(x, y)[0]])
+        });
+        let game_image: game::image::Image = board_image.into();
+        let texture = image_assets.add(game_image.into());
+
+        commands
+            .spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: LIGHT,
+                    custom_size: Some(Vec2::new(
+                        puzzle.image_width() as f32,
+                        puzzle.image_height() as f32,
+                    )),
+                    ..default()
+                },
+                texture,
+                ..default()
+            })
+            .insert(Board);
+        return;
+    }
+
     let puzzle_size = Vec2::new(puzzle.width() as f32, puzzle.height() as f32);
     let mesh = meshes.add(Mesh::from(shape::Quad::new(puzzle_size)));
 
@@ -0,0 +1,55 @@
+use bevy::prelude::*;
+
+use game::{PieceFlippedEvent, Puzzle};
+
+use crate::{
+    material::{PieceFace, PieceMaterial},
+    pieces::{HeldPiece, PieceMap},
+    states::AppState,
+};
+
+pub struct FlipPlugin;
+
+impl Plugin for FlipPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (flip_held_piece, show_piece_face)
+                .chain()
+                .run_if(in_state(AppState::Playing)),
+        );
+    }
+}
+
+fn flip_held_piece(
+    keys: Res<Input<KeyCode>>,
+    held_piece: Option<Res<HeldPiece>>,
+    mut puzzle: ResMut<Puzzle>,
+    mut piece_flipped_events: EventWriter<PieceFlippedEvent>,
+) {
+    if !keys.just_pressed(KeyCode::F) {
+        return;
+    }
+
+    if let Some(held_piece) = held_piece {
+        if let Some(event) = puzzle.flip_piece(&held_piece.index) {
+            piece_flipped_events.send(event);
+        }
+    }
+}
+
+fn show_piece_face(
+    mut piece_flipped_events: EventReader<PieceFlippedEvent>,
+    piece_map: Res<PieceMap>,
+    material_query: Query<&Handle<PieceMaterial>>,
+    mut materials: ResMut<Assets<PieceMaterial>>,
+) {
+    for event in piece_flipped_events.iter() {
+        let piece_entity = *piece_map.0.get(&event.index).unwrap();
+        if let Ok(material_handle) = material_query.get(piece_entity) {
+            if let Some(material) = materials.get_mut(material_handle) {
+                material.face = PieceFace::new(event.flipped);
+            }
+        }
+    }
+}
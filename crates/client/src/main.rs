@@ -4,8 +4,8 @@ use bevy::prelude::*;
 use bevy::{log::LogPlugin, time::common_conditions::on_timer};
 
 use game::{
-    PieceConnectionCheckEvent, PieceConnectionEvent, PieceMovedEvent, PiecePickedUpEvent,
-    PiecePutDownEvent, PlayerCursorMovedEvent, PlayerDisconnectedEvent, Puzzle,
+    PieceConnectionCheckEvent, PieceConnectionEvent, PieceFlippedEvent, PieceMovedEvent,
+    PiecePickedUpEvent, PiecePutDownEvent, PlayerCursorMovedEvent, PlayerDisconnectedEvent, Puzzle,
 };
 
 automod::dir!("src/");
@@ -13,10 +13,13 @@ automod::dir!("src/");
 use board::BoardPlugin;
 use cursors::CursorPlugin;
 use disable_context_menu::DisableContextMenuPlugin;
+use flip::FlipPlugin;
 use material::PieceMaterialPlugin;
 use mouse::MousePlugin;
 use network::NetworkPlugin;
 use pieces::PiecePlugin;
+use replay::{replay_url, ReplayPlugin};
+use snap_preview::SnapPreviewPlugin;
 use states::AppState;
 use ui::UiPlugin;
 use viewport::get_viewport_size;
@@ -31,47 +34,56 @@ fn main() {
         log_plugin.filter = "warn,client=debug,game=debug".into();
     }
 
-    App::new()
-        .add_plugins((
-            DefaultPlugins
-                .set(ImagePlugin::default_nearest())
-                .set(WindowPlugin {
-                    primary_window: Some(Window {
-                        fit_canvas_to_parent: true,
-                        prevent_default_event_handling: false,
-                        ..Default::default()
-                    }),
+    let mut app = App::new();
+    app.add_plugins((
+        DefaultPlugins
+            .set(ImagePlugin::default_nearest())
+            .set(WindowPlugin {
+                primary_window: Some(Window {
+                    fit_canvas_to_parent: true,
+                    prevent_default_event_handling: false,
                     ..Default::default()
-                })
-                .set(log_plugin),
-            DisableContextMenuPlugin,
-            MousePlugin,
-            PieceMaterialPlugin,
-            NetworkPlugin,
-            CursorPlugin,
-            PiecePlugin,
-            BoardPlugin,
-            UiPlugin,
-        ))
-        .add_state::<AppState>()
-        .add_event::<PieceMovedEvent>()
-        .add_event::<PiecePickedUpEvent>()
-        .add_event::<PiecePutDownEvent>()
-        .add_event::<PieceConnectionCheckEvent>()
-        .add_event::<PieceConnectionEvent>()
-        .add_event::<PlayerCursorMovedEvent>()
-        .add_event::<PlayerDisconnectedEvent>()
-        .add_systems(Startup, spawn_camera)
-        .add_systems(OnEnter(AppState::Cutting), init_camera)
-        .add_systems(Update, center_camera.run_if(in_state(AppState::Playing)))
-        .insert_resource(PuzzleComplete(false))
-        .add_systems(
-            Update,
-            puzzle_complete_check
-                .run_if(in_state(AppState::Playing))
-                .run_if(on_timer(Duration::from_millis(500))),
-        )
-        .run();
+                }),
+                ..Default::default()
+            })
+            .set(log_plugin),
+        DisableContextMenuPlugin,
+        MousePlugin,
+        PieceMaterialPlugin,
+        CursorPlugin,
+        PiecePlugin,
+        SnapPreviewPlugin,
+        FlipPlugin,
+        BoardPlugin,
+        UiPlugin,
+    ))
+    .add_state::<AppState>()
+    .add_event::<PieceMovedEvent>()
+    .add_event::<PiecePickedUpEvent>()
+    .add_event::<PiecePutDownEvent>()
+    .add_event::<PieceConnectionCheckEvent>()
+    .add_event::<PieceConnectionEvent>()
+    .add_event::<PieceFlippedEvent>()
+    .add_event::<PlayerCursorMovedEvent>()
+    .add_event::<PlayerDisconnectedEvent>()
+    .add_systems(Startup, spawn_camera)
+    .add_systems(OnEnter(AppState::Cutting), init_camera)
+    .add_systems(Update, center_camera.run_if(in_state(AppState::Playing)))
+    .insert_resource(PuzzleComplete(false))
+    .add_systems(
+        Update,
+        puzzle_complete_check
+            .run_if(in_state(AppState::Playing))
+            .run_if(on_timer(Duration::from_millis(500))),
+    );
+
+    // a ?replay=<url> page plays back a recording instead of joining a room
+    match replay_url() {
+        Some(url) => app.add_plugins(ReplayPlugin { url }),
+        None => app.add_plugins(NetworkPlugin),
+    };
+
+    app.run();
 }
 
 fn spawn_camera(mut commands: Commands) {
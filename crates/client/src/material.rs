@@ -1,7 +1,7 @@
 use bevy::{
     prelude::*,
     reflect::{TypePath, TypeUuid},
-    render::render_resource::{AsBindGroup, Shader, ShaderRef},
+    render::render_resource::{AsBindGroup, Shader, ShaderRef, ShaderType},
     sprite::{Material2d, Material2dPlugin},
 };
 
@@ -30,6 +30,27 @@ pub struct PieceMaterial {
     #[texture(2)]
     #[sampler(3)]
     pub mask_texture: Handle<Image>,
+    #[texture(4)]
+    #[sampler(5)]
+    pub back_texture: Handle<Image>,
+    #[uniform(6)]
+    pub face: PieceFace,
+}
+
+#[derive(ShaderType, Debug, Clone, Copy, Default)]
+pub struct PieceFace {
+    // 1.0 when the back is showing. padded out to 16 bytes for webgl
+    pub flipped: f32,
+    pub _padding: Vec3,
+}
+
+impl PieceFace {
+    pub fn new(flipped: bool) -> Self {
+        Self {
+            flipped: if flipped { 1.0 } else { 0.0 },
+            _padding: Vec3::ZERO,
+        }
+    }
 }
 
 impl Material2d for PieceMaterial {
@@ -13,6 +13,7 @@ use game::{
 
 use crate::{
     pieces::{HeldPiece, PieceComponent, PieceStack},
+    replay::Replay,
     states::AppState,
 };
 
@@ -52,20 +53,26 @@ impl Plugin for MousePlugin {
                     .run_if(in_state(AppState::Playing))
                     .after(world_cursor),
             )
+            // pieces can't be picked up while watching a replay
             .add_systems(
                 Update,
-                left_click.run_if(in_state(AppState::Playing)).after(pan),
+                left_click
+                    .run_if(in_state(AppState::Playing))
+                    .run_if(not(resource_exists::<Replay>()))
+                    .after(pan),
             )
             .add_systems(
                 Update,
                 click_piece
                     .run_if(in_state(AppState::Playing))
+                    .run_if(not(resource_exists::<Replay>()))
                     .after(left_click),
             )
             .add_systems(
                 Update,
                 drag_piece
                     .run_if(in_state(AppState::Playing))
+                    .run_if(not(resource_exists::<Replay>()))
                     .after(click_piece),
             );
     }
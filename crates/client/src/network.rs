@@ -5,13 +5,15 @@ use bevy::tasks::AsyncComputeTaskPool;
 use futures_util::future::join;
 use futures_util::{select, FutureExt, SinkExt, StreamExt};
 use game::{
-    AnyGameEvent, GameEvent, PieceConnectionCheckEvent, PieceConnectionEvent, PieceMovedEvent,
-    PiecePickedUpEvent, PiecePutDownEvent, PlayerCursorMovedEvent, PlayerDisconnectedEvent, Puzzle,
+    AnyGameEvent, GameEvent, PieceConnectionCheckEvent, PieceConnectionEvent, PieceFlippedEvent,
+    PieceMovedEvent, PiecePickedUpEvent, PiecePutDownEvent, PlayerCursorMovedEvent,
+    PlayerDisconnectedEvent, Puzzle,
 };
 use tokio::sync::mpsc::error::TryRecvError;
 use tokio::sync::oneshot;
 use ws_stream_wasm::{WsMessage, WsMeta};
 
+use crate::pieces::HeldPiece;
 use crate::states::AppState;
 use crate::ui::LoadingMessage;
 use crate::worker::Worker;
@@ -42,11 +44,20 @@ fn spawn_network_io_task(
         let document = window.document().unwrap();
         let location = document.location().unwrap();
         let host = location.host().unwrap();
+        let path = location.pathname().unwrap();
+
+        // the page at /room/<room id> plays in that room, anywhere else in the default room
+        let client_path = match path.strip_prefix("/room/") {
+            Some(room_id) if !room_id.trim_end_matches('/').is_empty() => {
+                format!("client/{}", room_id.trim_end_matches('/'))
+            }
+            _ => String::from("client"),
+        };
 
         let ws_address = if cfg!(debug_assertions) {
-            format!("ws://{host}/client")
+            format!("ws://{host}/{client_path}")
         } else {
-            format!("wss://{host}/client")
+            format!("wss://{host}/{client_path}")
         };
 
         let ws_io = match WsMeta::connect(ws_address.as_str(), None).await {
@@ -131,6 +142,9 @@ struct EventIoParams<'w, 's> {
     piece_connection_events: ResMut<'w, Events<PieceConnectionEvent>>,
     piece_connection_reader: Local<'s, ManualEventReader<PieceConnectionEvent>>,
 
+    piece_flipped_events: ResMut<'w, Events<PieceFlippedEvent>>,
+    piece_flipped_reader: Local<'s, ManualEventReader<PieceFlippedEvent>>,
+
     player_cursor_moved_events: ResMut<'w, Events<PlayerCursorMovedEvent>>,
     player_cursor_moved_reader: Local<'s, ManualEventReader<PlayerCursorMovedEvent>>,
 
@@ -138,11 +152,27 @@ struct EventIoParams<'w, 's> {
     player_disconnected_reader: Local<'s, ManualEventReader<PlayerDisconnectedEvent>>,
 }
 
+impl EventIoParams<'_, '_> {
+    // drop everything queued in either direction
+    fn clear(&mut self) {
+        self.piece_moved_events.clear();
+        self.piece_picked_up_events.clear();
+        self.piece_put_down_events.clear();
+        self.piece_connection_check_events.clear();
+        self.piece_connection_events.clear();
+        self.piece_flipped_events.clear();
+        self.player_cursor_moved_events.clear();
+        self.player_disconnected_events.clear();
+    }
+}
+
 fn event_io(
+    mut commands: Commands,
     mut params: EventIoParams,
     mut network_io: ResMut<NetworkIO>,
     mut puzzle: ResMut<Puzzle>,
     mut next_state: ResMut<NextState<AppState>>,
+    mut loading_msg: ResMut<LoadingMessage>,
 ) {
     // forward all events generated by the client to the server
 
@@ -162,6 +192,7 @@ fn event_io(
     forward_events!(piece_put_down_reader, piece_put_down_events);
     forward_events!(piece_connection_check_reader, piece_connection_check_events);
     forward_events!(piece_connection_reader, piece_connection_events);
+    forward_events!(piece_flipped_reader, piece_flipped_events);
     forward_events!(player_cursor_moved_reader, player_cursor_moved_events);
     forward_events!(player_disconnected_reader, player_disconnected_events);
 
@@ -169,6 +200,17 @@ fn event_io(
     let mut new_events = Vec::new();
     while let Ok(msg) = network_io.output.try_recv() {
         let event = AnyGameEvent::deserialize(msg.as_str()).unwrap();
+        if let AnyGameEvent::PuzzleChanged(_) = event {
+            // nothing about the old puzzle carries over, its piece indices may not even
+            // exist in the new one
+            commands.remove_resource::<HeldPiece>();
+            params.clear();
+
+            // the new puzzle is the next message, same as right after connecting
+            next_state.set(AppState::Downloading);
+            loading_msg.0 = String::from("Loading next puzzle");
+            return;
+        }
         new_events.extend(puzzle.apply_event(event));
     }
 
@@ -181,8 +223,10 @@ fn event_io(
             PiecePutDown(event) => params.piece_put_down_events.send(event),
             PieceConnectionCheck(event) => params.piece_connection_check_events.send(event),
             PieceConnection(event) => params.piece_connection_events.send(event),
+            PieceFlipped(event) => params.piece_flipped_events.send(event),
             PlayerCursorMoved(event) => params.player_cursor_moved_events.send(event),
             PlayerDisconnected(event) => params.player_disconnected_events.send(event),
+            PuzzleChanged(_) => (),
         }
     }
 
@@ -200,6 +244,9 @@ fn event_io(
     params
         .piece_connection_reader
         .clear(&params.piece_connection_events);
+    params
+        .piece_flipped_reader
+        .clear(&params.piece_flipped_events);
     params
         .player_cursor_moved_reader
         .clear(&params.player_cursor_moved_events);
@@ -7,7 +7,10 @@ use bevy::{
 use game::{PieceIndex, PieceKind, PieceMovedEvent, Puzzle};
 
 use crate::{
-    better_quad::BetterQuad, material::PieceMaterial, states::AppState, ui::LoadingMessage,
+    better_quad::BetterQuad,
+    material::{PieceFace, PieceMaterial},
+    states::AppState,
+    ui::LoadingMessage,
 };
 
 pub const MIN_PIECE_HEIGHT: f32 = 500.0;
@@ -47,6 +50,9 @@ impl PieceComponent {
     }
 }
 
+#[derive(Component)]
+pub struct PieceShadow;
+
 struct CachedPieceShape {
     mask_handle: Handle<Image>,
     shadow_handle: Handle<Image>,
@@ -56,6 +62,36 @@ struct CachedPieceShape {
     shadow_y_offset: f32,
 }
 
+impl CachedPieceShape {
+    fn new(
+        mask_sprite: game::image::Sprite,
+        shadow_sprite: game::image::Sprite,
+        image_assets: &mut Assets<Image>,
+    ) -> Self {
+        let sprite_size = Vec2::new(
+            mask_sprite.image.width() as f32,
+            mask_sprite.image.height() as f32,
+        );
+        let sprite_origin = Vec2::new(mask_sprite.origin_x as f32, mask_sprite.origin_y as f32);
+        let shadow_x_offset =
+            shadow_sprite.image.width() as f32 / 2.0 - shadow_sprite.origin_x as f32;
+        let shadow_y_offset =
+            shadow_sprite.image.height() as f32 / 2.0 - shadow_sprite.origin_y as f32;
+
+        let mask_handle = image_assets.add(mask_sprite.image.into());
+        let shadow_handle = image_assets.add(shadow_sprite.image.into());
+
+        Self {
+            mask_handle,
+            shadow_handle,
+            sprite_size,
+            sprite_origin,
+            shadow_x_offset,
+            shadow_y_offset,
+        }
+    }
+}
+
 #[derive(Resource)]
 struct PieceShapeCache(HashMap<PieceKind, CachedPieceShape>);
 
@@ -70,7 +106,8 @@ impl PieceBundle {
         index: PieceIndex,
         translation: Vec3,
         cached_shape: &CachedPieceShape,
-        puzzle_texture: Handle<Image>,
+        puzzle_texture: &PuzzleTexture,
+        flipped: bool,
         crop_x: u32,
         crop_y: u32,
         full_width: u32,
@@ -121,8 +158,10 @@ impl PieceBundle {
         let mesh_handle = meshes.add(mesh);
 
         let material = materials.add(PieceMaterial {
-            puzzle_texture,
+            puzzle_texture: puzzle_texture.front.clone(),
             mask_texture: cached_shape.mask_handle.clone(),
+            back_texture: puzzle_texture.back.clone(),
+            face: PieceFace::new(flipped),
         });
 
         let mut translation = translation;
@@ -148,11 +187,18 @@ pub struct PieceMap(pub HashMap<PieceIndex, Entity>);
 pub struct PieceStack(pub VecDeque<Entity>);
 
 #[derive(Resource)]
-struct PuzzleTexture(pub Handle<Image>);
+struct PuzzleTexture {
+    front: Handle<Image>,
+    // same as the front for single sided puzzles
+    back: Handle<Image>,
+}
 
 #[derive(Resource)]
 struct CurrentPieceToCut(pub u32);
 
+#[derive(Resource)]
+struct PiecesToCut(pub Vec<PieceIndex>);
+
 impl PieceStack {
     fn remove_entity(&mut self, entity: Entity) {
         self.0
@@ -185,43 +231,37 @@ fn cutting_setup(
     commands.insert_resource(PieceMap(HashMap::new()));
     commands.insert_resource(PieceStack(VecDeque::new()));
     commands.insert_resource(CurrentPieceToCut(0));
+    commands.insert_resource(PiecesToCut(puzzle.piece_indices()));
 
     let rgba_image = puzzle.rgba_image();
     let game_image: game::image::Image = rgba_image.into();
     let bevy_image: Image = game_image.into();
     let texture_handle = image_assets.add(bevy_image);
-    commands.insert_resource(PuzzleTexture(texture_handle));
-
-    // Pre-compute mask and shadow textures for all 17 piece kinds
-    let mut shape_cache = HashMap::new();
-    for kind in PieceKind::ALL {
-        let (mask_sprite, shadow_sprite) =
-            kind.render_mask_and_shadow(puzzle.piece_width(), puzzle.piece_height());
 
-        let sprite_size = Vec2::new(
-            mask_sprite.image.width() as f32,
-            mask_sprite.image.height() as f32,
-        );
-        let sprite_origin = Vec2::new(mask_sprite.origin_x as f32, mask_sprite.origin_y as f32);
-        let shadow_x_offset =
-            shadow_sprite.image.width() as f32 / 2.0 - shadow_sprite.origin_x as f32;
-        let shadow_y_offset =
-            shadow_sprite.image.height() as f32 / 2.0 - shadow_sprite.origin_y as f32;
+    let back_handle = match puzzle.back_rgba_image() {
+        Some(back_image) => {
+            let game_image: game::image::Image = back_image.into();
+            image_assets.add(game_image.into())
+        }
+        None => texture_handle.clone(),
+    };
 
-        let mask_handle = image_assets.add(mask_sprite.image.into());
-        let shadow_handle = image_assets.add(shadow_sprite.image.into());
+    commands.insert_resource(PuzzleTexture {
+        front: texture_handle,
+        back: back_handle,
+    });
 
-        shape_cache.insert(
-            kind,
-            CachedPieceShape {
-                mask_handle,
-                shadow_handle,
-                sprite_size,
-                sprite_origin,
-                shadow_x_offset,
-                shadow_y_offset,
-            },
-        );
+    // Pre-compute mask and shadow textures for all 17 piece kinds
+    let mut shape_cache = HashMap::new();
+    if puzzle.uses_piece_kinds() {
+        for kind in PieceKind::ALL {
+            let (mask_sprite, shadow_sprite) =
+                kind.render_mask_and_shadow(puzzle.piece_width(), puzzle.piece_height());
+            shape_cache.insert(
+                kind,
+                CachedPieceShape::new(mask_sprite, shadow_sprite, &mut image_assets),
+            );
+        }
     }
     commands.insert_resource(PieceShapeCache(shape_cache));
 
@@ -240,9 +280,11 @@ fn cut_pieces(
     puzzle_texture: Res<PuzzleTexture>,
     shape_cache: Res<PieceShapeCache>,
     mut current_piece: ResMut<CurrentPieceToCut>,
+    pieces_to_cut: Res<PiecesToCut>,
     puzzle: Res<Puzzle>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<PieceMaterial>>,
+    mut image_assets: ResMut<Assets<Image>>,
     mut loading_msg: ResMut<LoadingMessage>,
     mut piece_map: ResMut<PieceMap>,
     mut piece_stack: ResMut<PieceStack>,
@@ -258,24 +300,31 @@ fn cut_pieces(
     );
 
     while current_piece.0 < batch_end {
-        let index = PieceIndex(
-            current_piece.0 / puzzle.num_cols(),
-            current_piece.0 % puzzle.num_cols(),
-        );
+        let index = pieces_to_cut.0[current_piece.0 as usize];
 
         let piece = puzzle.piece(&index).unwrap();
-        let cached_shape = shape_cache.0.get(&piece.kind()).unwrap();
-        let (crop_x, crop_y) = piece.crop_offset(puzzle.as_ref());
+        let piece_shape;
+        let (cached_shape, crop_x, crop_y) = if puzzle.uses_piece_kinds() {
+            let (crop_x, crop_y) = piece.crop_offset(puzzle.as_ref());
+            (shape_cache.0.get(&piece.kind()).unwrap(), crop_x, crop_y)
+        } else {
+            // other cuts and whimsies don't repeat shapes, so each piece gets its own mask
+            let (mask_sprite, shadow_sprite, crop_x, crop_y) =
+                piece.cut_mask_and_shadow(puzzle.as_ref());
+            piece_shape = CachedPieceShape::new(mask_sprite, shadow_sprite, &mut image_assets);
+            (&piece_shape, crop_x, crop_y)
+        };
 
         let piece_bundle = PieceBundle::new(
             index,
             piece.translation(),
             cached_shape,
-            puzzle_texture.0.clone(),
+            &puzzle_texture,
+            piece.flipped(),
             crop_x,
             crop_y,
-            puzzle.width(),
-            puzzle.height(),
+            puzzle.image_width(),
+            puzzle.image_height(),
             &mut meshes,
             &mut materials,
         );
@@ -290,7 +339,7 @@ fn cut_pieces(
             texture: cached_shape.shadow_handle.clone(),
             ..Default::default()
         };
-        let shadow_entity = commands.spawn(shadow).id();
+        let shadow_entity = commands.spawn(shadow).insert(PieceShadow).id();
 
         commands
             .entity(piece_entity)
@@ -0,0 +1,554 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::tasks::AsyncComputeTaskPool;
+use bevy::utils::HashMap;
+use game::{
+    AnyGameEvent, PieceConnectionCheckEvent, PieceConnectionEvent, PieceFlippedEvent,
+    PieceMovedEvent, PiecePickedUpEvent, PiecePutDownEvent, PlayerCursorMovedEvent,
+    PlayerDisconnectedEvent, Puzzle, Recording, Uuid,
+};
+use tokio::sync::mpsc::error::TryRecvError;
+
+use crate::{
+    colors::{DARK, LIGHTER, MED},
+    states::AppState,
+    ui::{LoadingMessage, UiFont},
+    util::despawn,
+    worker::Worker,
+};
+
+const SPEEDS: [f64; 7] = [0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0];
+const DEFAULT_SPEED_INDEX: usize = 1;
+const SEEK_STEP_MILLIS: f64 = 10_000.0;
+const TRACK_SEGMENTS: usize = 60;
+
+// plays back a recording from the server's admin api instead of joining a room. pieces and
+// cursors move as they did in the recording, and the mouse only pans and zooms
+pub struct ReplayPlugin {
+    pub url: String,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(RecordingUrl(self.url.clone()))
+            .add_systems(OnEnter(AppState::Connecting), spawn_download_task)
+            .add_systems(
+                Update,
+                load_recording.run_if(in_state(AppState::Downloading)),
+            )
+            .add_systems(OnEnter(AppState::Playing), spawn_controls)
+            .add_systems(OnExit(AppState::Playing), despawn_controls)
+            .add_systems(
+                Update,
+                (
+                    keyboard_controls,
+                    button_controls,
+                    scrub,
+                    advance,
+                    update_controls,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+// the recording to play from a ?replay=<url> page, which reqwest needs to be absolute
+pub fn replay_url() -> Option<String> {
+    let url = query_param("replay")?;
+    if url.starts_with("http://") || url.starts_with("https://") {
+        Some(url)
+    } else {
+        let origin = location().origin().unwrap();
+        Some(format!("{origin}/{}", url.trim_start_matches('/')))
+    }
+}
+
+fn query_param(name: &str) -> Option<String> {
+    location()
+        .search()
+        .unwrap()
+        .trim_start_matches('?')
+        .split('&')
+        .find_map(|param| param.strip_prefix(name)?.strip_prefix('='))
+        .filter(|value| !value.is_empty())
+        .map(String::from)
+}
+
+fn location() -> web_sys::Location {
+    let window = web_sys::window().unwrap();
+    window.document().unwrap().location().unwrap()
+}
+
+#[derive(Resource)]
+struct RecordingUrl(String);
+
+type RecordingDownload = Worker<(), Result<String, String>>;
+
+#[derive(Resource)]
+pub struct Replay {
+    recording: Recording,
+    // the next event in the recording to apply
+    next_event: usize,
+    millis: f64,
+    playing: bool,
+    speed_index: usize,
+    // players with a cursor on screen
+    cursors: HashMap<Uuid, PlayerCursorMovedEvent>,
+}
+
+impl Replay {
+    fn duration(&self) -> f64 {
+        self.recording.duration_millis() as f64
+    }
+
+    fn finished(&self) -> bool {
+        self.next_event >= self.recording.events.len()
+    }
+
+    // apply every event up to the given time, returning the ones bevy should hear about
+    fn play_until(&mut self, millis: f64, puzzle: &mut Puzzle) -> Vec<AnyGameEvent> {
+        let mut new_events = Vec::new();
+        while let Some(recorded) = self.recording.events.get(self.next_event) {
+            if recorded.millis as f64 > millis {
+                break;
+            }
+            self.next_event += 1;
+
+            for event in puzzle.apply_event(recorded.event.clone()) {
+                match event {
+                    // the server answers a connection check with the connection it made, which
+                    // clients apply to get the pieces' new positions
+                    AnyGameEvent::PieceConnection(_) => {
+                        new_events.extend(puzzle.apply_event(event));
+                    }
+                    AnyGameEvent::PlayerCursorMoved(cursor_event) => {
+                        if let Some(player_id) = cursor_event.player_id {
+                            self.cursors.insert(player_id, cursor_event);
+                        }
+                        new_events.push(event);
+                    }
+                    AnyGameEvent::PlayerDisconnected(ref disconnected_event) => {
+                        self.cursors.remove(&disconnected_event.player_id);
+                        new_events.push(event);
+                    }
+                    _ => new_events.push(event),
+                }
+            }
+        }
+        self.millis = millis;
+        new_events
+    }
+
+    // events can't be undone, so going backwards starts over from the recording's first puzzle.
+    // the pieces and cursors then get put straight where they are at the new time
+    fn seek(&mut self, millis: f64, puzzle: &mut Puzzle) -> Vec<AnyGameEvent> {
+        let millis = millis.clamp(0.0, self.duration());
+        let old_cursors: Vec<Uuid> = self.cursors.keys().copied().collect();
+
+        if millis < self.millis {
+            *puzzle = self.recording.initial_puzzle().unwrap();
+            self.next_event = 0;
+            self.cursors.clear();
+        }
+        self.play_until(millis, puzzle);
+
+        let mut new_events: Vec<AnyGameEvent> = old_cursors
+            .into_iter()
+            .filter(|player_id| !self.cursors.contains_key(player_id))
+            .map(|player_id| {
+                AnyGameEvent::PlayerDisconnected(PlayerDisconnectedEvent { player_id })
+            })
+            .collect();
+
+        for index in puzzle.piece_indices() {
+            let piece = puzzle.piece(&index).unwrap();
+            new_events.push(AnyGameEvent::PieceMoved(PieceMovedEvent::from(piece)));
+            new_events.push(AnyGameEvent::PieceFlipped(PieceFlippedEvent {
+                player_id: None,
+                index,
+                flipped: piece.flipped(),
+            }));
+        }
+
+        new_events.extend(
+            self.cursors
+                .values()
+                .map(|cursor_event| AnyGameEvent::PlayerCursorMoved(*cursor_event)),
+        );
+        new_events
+    }
+}
+
+#[derive(SystemParam)]
+struct GameEventWriters<'w> {
+    piece_moved: EventWriter<'w, PieceMovedEvent>,
+    piece_picked_up: EventWriter<'w, PiecePickedUpEvent>,
+    piece_put_down: EventWriter<'w, PiecePutDownEvent>,
+    piece_connection_check: EventWriter<'w, PieceConnectionCheckEvent>,
+    piece_connection: EventWriter<'w, PieceConnectionEvent>,
+    piece_flipped: EventWriter<'w, PieceFlippedEvent>,
+    player_cursor_moved: EventWriter<'w, PlayerCursorMovedEvent>,
+    player_disconnected: EventWriter<'w, PlayerDisconnectedEvent>,
+}
+
+impl GameEventWriters<'_> {
+    fn send_all(&mut self, events: Vec<AnyGameEvent>) {
+        for event in events {
+            use AnyGameEvent::*;
+            match event {
+                PieceMoved(event) => self.piece_moved.send(event),
+                PiecePickedUp(event) => self.piece_picked_up.send(event),
+                PiecePutDown(event) => self.piece_put_down.send(event),
+                PieceConnectionCheck(event) => self.piece_connection_check.send(event),
+                PieceConnection(event) => self.piece_connection.send(event),
+                PieceFlipped(event) => self.piece_flipped.send(event),
+                PlayerCursorMoved(event) => self.player_cursor_moved.send(event),
+                PlayerDisconnected(event) => self.player_disconnected.send(event),
+                PuzzleChanged(_) => (),
+            }
+        }
+    }
+}
+
+fn spawn_download_task(
+    mut commands: Commands,
+    url: Res<RecordingUrl>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut loading_msg: ResMut<LoadingMessage>,
+) {
+    let url = url.0.clone();
+    // asked for rather than taken from the page url, so it stays out of history and logs
+    let token = web_sys::window()
+        .unwrap()
+        .prompt_with_message("Admin token for the recording, if it needs one")
+        .ok()
+        .flatten()
+        .filter(|token| !token.trim().is_empty());
+    let thread_pool = AsyncComputeTaskPool::get();
+    let download = RecordingDownload::spawn(thread_pool, |_, tx| async move {
+        let mut request = reqwest::Client::new().get(url.as_str());
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let res = match request.send().await {
+            Ok(response) if !response.status().is_success() => {
+                Err(format!("the server answered {}", response.status()))
+            }
+            Ok(response) => response.text().await.map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        let _ = tx.send(res);
+    });
+    commands.insert_resource(download);
+    next_state.set(AppState::Downloading);
+    loading_msg.0 = String::from("Downloading recording");
+}
+
+fn load_recording(
+    mut commands: Commands,
+    mut download: ResMut<RecordingDownload>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut loading_msg: ResMut<LoadingMessage>,
+) {
+    let res = match download.output.try_recv() {
+        Ok(res) => res,
+        Err(TryRecvError::Empty) => return,
+        Err(TryRecvError::Disconnected) => Err(String::from("download stopped")),
+    };
+
+    let loaded = res.and_then(|text| {
+        let recording = Recording::deserialize(&text).map_err(|e| e.to_string())?;
+        let puzzle = recording.initial_puzzle().map_err(|e| e.to_string())?;
+        Ok((recording, puzzle))
+    });
+
+    match loaded {
+        Ok((recording, puzzle)) => {
+            commands.insert_resource(puzzle);
+            commands.insert_resource(Replay {
+                recording,
+                next_event: 0,
+                millis: 0.0,
+                playing: false,
+                speed_index: DEFAULT_SPEED_INDEX,
+                cursors: HashMap::new(),
+            });
+            commands.remove_resource::<RecordingDownload>();
+            next_state.set(AppState::Cutting);
+        }
+        Err(e) => {
+            warn!("Couldn't load recording: {e}");
+            commands.remove_resource::<RecordingDownload>();
+            loading_msg.0 = format!("Couldn't load the recording: {e}");
+        }
+    }
+}
+
+fn advance(
+    mut replay: ResMut<Replay>,
+    mut puzzle: ResMut<Puzzle>,
+    mut writers: GameEventWriters,
+    time: Res<Time>,
+) {
+    if !replay.playing {
+        return;
+    }
+
+    let millis = replay.millis + time.delta_seconds_f64() * 1000.0 * SPEEDS[replay.speed_index];
+    let new_events = replay.play_until(millis, &mut puzzle);
+    writers.send_all(new_events);
+
+    if replay.finished() {
+        replay.playing = false;
+    }
+}
+
+// p to play or pause, left and right to skip, up and down to change speed
+fn keyboard_controls(
+    keys: Res<Input<KeyCode>>,
+    mut replay: ResMut<Replay>,
+    mut puzzle: ResMut<Puzzle>,
+    mut writers: GameEventWriters,
+) {
+    if keys.just_pressed(KeyCode::P) {
+        toggle_playing(&mut replay, &mut puzzle, &mut writers);
+    }
+    if keys.just_pressed(KeyCode::Up) {
+        replay.speed_index = (replay.speed_index + 1).min(SPEEDS.len() - 1);
+    }
+    if keys.just_pressed(KeyCode::Down) {
+        replay.speed_index = replay.speed_index.saturating_sub(1);
+    }
+
+    let step = if keys.just_pressed(KeyCode::Left) {
+        -SEEK_STEP_MILLIS
+    } else if keys.just_pressed(KeyCode::Right) {
+        SEEK_STEP_MILLIS
+    } else {
+        return;
+    };
+    let millis = replay.millis + step;
+    let new_events = replay.seek(millis, &mut puzzle);
+    writers.send_all(new_events);
+}
+
+fn toggle_playing(replay: &mut Replay, puzzle: &mut Puzzle, writers: &mut GameEventWriters) {
+    // playing from the end starts again from the beginning
+    if !replay.playing && replay.finished() {
+        let new_events = replay.seek(0.0, puzzle);
+        writers.send_all(new_events);
+    }
+    replay.playing = !replay.playing;
+}
+
+#[derive(Component)]
+struct ReplayControlsNode;
+
+#[derive(Component)]
+struct PlayButton;
+
+#[derive(Component)]
+struct PlayText;
+
+#[derive(Component)]
+struct SpeedButton;
+
+#[derive(Component)]
+struct SpeedText;
+
+#[derive(Component)]
+struct TimeText;
+
+// one of the clickable pieces of the progress bar, at this fraction of the recording
+#[derive(Component)]
+struct TrackSegment(f64);
+
+fn spawn_controls(mut commands: Commands, font: Res<UiFont>) {
+    let text_style = TextStyle {
+        font: font.0.clone(),
+        font_size: 25.0,
+        color: LIGHTER,
+    };
+    let button_style = Style {
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        margin: UiRect::all(Val::Px(5.0)),
+        padding: UiRect::horizontal(Val::Px(10.0)),
+        min_width: Val::Px(80.0),
+        ..default()
+    };
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Start,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(ReplayControlsNode)
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        margin: UiRect::all(Val::Px(10.0)),
+                        padding: UiRect::all(Val::Px(5.0)),
+                        ..default()
+                    },
+                    background_color: DARK.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(ButtonBundle {
+                            style: button_style.clone(),
+                            background_color: MED.into(),
+                            ..default()
+                        })
+                        .insert(PlayButton)
+                        .with_children(|parent| {
+                            parent
+                                .spawn(TextBundle::from_section("", text_style.clone()))
+                                .insert(PlayText);
+                        });
+
+                    parent
+                        .spawn(ButtonBundle {
+                            style: button_style,
+                            background_color: MED.into(),
+                            ..default()
+                        })
+                        .insert(SpeedButton)
+                        .with_children(|parent| {
+                            parent
+                                .spawn(TextBundle::from_section("", text_style.clone()))
+                                .insert(SpeedText);
+                        });
+
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                height: Val::Px(20.0),
+                                margin: UiRect::all(Val::Px(5.0)),
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            for i in 0..TRACK_SEGMENTS {
+                                parent
+                                    .spawn(ButtonBundle {
+                                        style: Style {
+                                            width: Val::Px(5.0),
+                                            height: Val::Percent(100.0),
+                                            ..default()
+                                        },
+                                        background_color: MED.into(),
+                                        ..default()
+                                    })
+                                    .insert(TrackSegment(i as f64 / TRACK_SEGMENTS as f64));
+                            }
+                        });
+
+                    parent
+                        .spawn(TextBundle::from_section("", text_style).with_style(Style {
+                            margin: UiRect::horizontal(Val::Px(5.0)),
+                            ..default()
+                        }))
+                        .insert(TimeText);
+                });
+        });
+}
+
+fn despawn_controls(
+    mut commands: Commands,
+    controls_query: Query<Entity, With<ReplayControlsNode>>,
+) {
+    despawn(controls_query, &mut commands);
+}
+
+#[allow(clippy::type_complexity)]
+fn button_controls(
+    play_query: Query<&Interaction, (Changed<Interaction>, With<PlayButton>)>,
+    speed_query: Query<&Interaction, (Changed<Interaction>, With<SpeedButton>)>,
+    mut replay: ResMut<Replay>,
+    mut puzzle: ResMut<Puzzle>,
+    mut writers: GameEventWriters,
+) {
+    if play_query.iter().any(|i| *i == Interaction::Clicked) {
+        toggle_playing(&mut replay, &mut puzzle, &mut writers);
+    }
+    if speed_query.iter().any(|i| *i == Interaction::Clicked) {
+        replay.speed_index = (replay.speed_index + 1) % SPEEDS.len();
+    }
+}
+
+// click on the progress bar to jump there, or drag along it
+fn scrub(
+    segment_query: Query<(&Interaction, &TrackSegment), Changed<Interaction>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut replay: ResMut<Replay>,
+    mut puzzle: ResMut<Puzzle>,
+    mut writers: GameEventWriters,
+) {
+    let target = segment_query
+        .iter()
+        .filter(|(interaction, _)| match interaction {
+            Interaction::Clicked => true,
+            Interaction::Hovered => mouse_buttons.pressed(MouseButton::Left),
+            Interaction::None => false,
+        })
+        .map(|(_, segment)| segment.0)
+        .last();
+
+    if let Some(fraction) = target {
+        let millis = fraction * replay.duration();
+        let new_events = replay.seek(millis, &mut puzzle);
+        writers.send_all(new_events);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_controls(
+    replay: Res<Replay>,
+    mut play_text_query: Query<&mut Text, (With<PlayText>, Without<SpeedText>, Without<TimeText>)>,
+    mut speed_text_query: Query<&mut Text, (With<SpeedText>, Without<PlayText>, Without<TimeText>)>,
+    mut time_text_query: Query<&mut Text, (With<TimeText>, Without<PlayText>, Without<SpeedText>)>,
+    mut segment_query: Query<(&TrackSegment, &mut BackgroundColor)>,
+) {
+    if !replay.is_changed() {
+        return;
+    }
+
+    play_text_query.single_mut().sections[0].value = String::from(match replay.playing {
+        true => "Pause",
+        false => "Play",
+    });
+    speed_text_query.single_mut().sections[0].value = format!("{}x", SPEEDS[replay.speed_index]);
+    time_text_query.single_mut().sections[0].value = format!(
+        "{} / {}",
+        format_time(replay.millis),
+        format_time(replay.duration())
+    );
+
+    let progress = match replay.duration() {
+        duration if duration > 0.0 => replay.millis / duration,
+        _ => 1.0,
+    };
+    for (segment, mut color) in segment_query.iter_mut() {
+        *color = match segment.0 < progress {
+            true => LIGHTER.into(),
+            false => MED.into(),
+        };
+    }
+}
+
+fn format_time(millis: f64) -> String {
+    let seconds = (millis / 1000.0) as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+
+use game::Puzzle;
+
+use crate::{
+    pieces::{HeldPiece, PieceComponent, PieceMap, PieceShadow, MIN_PIECE_HEIGHT},
+    states::AppState,
+    util::despawn,
+};
+
+// the shadow texture is solid black, so this just controls how dark the ghost is
+const GHOST_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.35);
+
+// above every piece shadow but below every piece
+const GHOST_HEIGHT: f32 = MIN_PIECE_HEIGHT - 1.0;
+
+pub struct SnapPreviewPlugin;
+
+impl Plugin for SnapPreviewPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::Playing), spawn_ghost)
+            .add_systems(OnExit(AppState::Playing), despawn_ghost)
+            .add_systems(Update, snap_preview.run_if(in_state(AppState::Playing)));
+    }
+}
+
+#[derive(Component)]
+struct SnapGhost;
+
+fn spawn_ghost(mut commands: Commands) {
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: GHOST_COLOR,
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(SnapGhost);
+}
+
+fn despawn_ghost(mut commands: Commands, ghost_query: Query<Entity, With<SnapGhost>>) {
+    despawn(ghost_query, &mut commands);
+}
+
+#[allow(clippy::type_complexity)]
+fn snap_preview(
+    held_piece: Option<Res<HeldPiece>>,
+    puzzle: Res<Puzzle>,
+    piece_map: Res<PieceMap>,
+    children_query: Query<&Children, With<PieceComponent>>,
+    shadow_query: Query<(&Handle<Image>, &Transform), (With<PieceShadow>, Without<SnapGhost>)>,
+    mut ghost_query: Query<
+        (&mut Transform, &mut Handle<Image>, &mut Visibility),
+        (With<SnapGhost>, Without<PieceShadow>),
+    >,
+) {
+    let Ok((mut ghost_transform, mut ghost_texture, mut ghost_visibility)) =
+        ghost_query.get_single_mut()
+    else {
+        return;
+    };
+
+    // run the same check the server will run when the piece is put down
+    let preview = held_piece
+        .as_deref()
+        .and_then(|held_piece| puzzle.connection_preview(&held_piece.index));
+
+    // outline the held piece that would connect at its snap target, using its own shadow texture
+    let shadow = preview.and_then(|(index, target)| {
+        let piece_entity = piece_map.0.get(&index)?;
+        let shadow = children_query
+            .get(*piece_entity)
+            .ok()?
+            .iter()
+            .find_map(|child| shadow_query.get(*child).ok())?;
+        Some((target, shadow))
+    });
+
+    if let Some((target, (shadow_texture, shadow_transform))) = shadow {
+        ghost_transform.translation = Vec3::new(
+            target.x + shadow_transform.translation.x,
+            target.y + shadow_transform.translation.y,
+            GHOST_HEIGHT,
+        );
+        *ghost_texture = shadow_texture.clone();
+        *ghost_visibility = Visibility::Visible;
+    } else {
+        *ghost_visibility = Visibility::Hidden;
+    }
+}
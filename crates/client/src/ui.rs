@@ -49,7 +49,7 @@ impl Plugin for UiPlugin {
 }
 
 #[derive(Resource)]
-struct UiFont(Handle<Font>);
+pub struct UiFont(pub Handle<Font>);
 
 #[derive(Component)]
 struct LoadingNode;
@@ -209,6 +209,7 @@ const HELP_SYMBOL: &str = "?";
 const HELP_TEXT: &str = "• Left click to grab and place pieces, or click and drag to move them\n\
                         • Right or middle click and drag to pan\n\
                         • Scroll to zoom\n\
+                        • Press F to flip the piece you're holding, if the puzzle has a back\n\
                         • Press space to center the camera\n\n\
                         Made by Harrison Gieraltowski - harrisonmg.net";
 
@@ -372,7 +373,17 @@ fn hover_image_download(
                 style.height = BUTTON_SIZE;
             }
             Interaction::Hovered => {
-                text.sections[0].value = String::from(IMAGE_DOWNLOAD_TEXT);
+                let options = puzzle.options();
+                let credits: Vec<&str> = [&options.title, &options.attribution]
+                    .into_iter()
+                    .flatten()
+                    .map(String::as_str)
+                    .collect();
+                text.sections[0].value = if credits.is_empty() {
+                    String::from(IMAGE_DOWNLOAD_TEXT)
+                } else {
+                    format!("{}\n\n{IMAGE_DOWNLOAD_TEXT}", credits.join("\n"))
+                };
                 style.width = Val::Auto;
                 style.height = Val::Auto;
             }
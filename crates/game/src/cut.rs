@@ -0,0 +1,469 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::PieceIndex;
+
+// how far irregular cut corners can wander from the grid, as a ratio of the piece size
+const IRREGULAR_JITTER: f64 = 0.18;
+// curved tab size and how much its shape varies, as ratios of the edge length
+const CURVED_TAB_SIZE: f64 = 0.1;
+const CURVED_TAB_JITTER: f64 = 0.04;
+const CURVE_SAMPLES: usize = 12;
+// whimsies take the place of a 2x2 cluster of cells, their silhouette fills this much of it
+const WHIMSY_SIZE_RATIO: f64 = 0.7;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CutStyle {
+    // classic rectangular pieces with square tabs
+    #[default]
+    Grid,
+    // pointy-topped hexagons, offset every other row
+    Hexagonal,
+    // a grid with jittered corners and curved tabs, so no two pieces are the same shape
+    Irregular,
+}
+
+impl FromStr for CutStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "grid" => Ok(Self::Grid),
+            "hexagonal" | "hex" => Ok(Self::Hexagonal),
+            "irregular" => Ok(Self::Irregular),
+            _ => Err(anyhow!("unknown cut style: {s}")),
+        }
+    }
+}
+
+// which pieces a cut produces, where they sit in the solved puzzle and which of them share an edge.
+// everything is in image pixels measured from the top left corner of the puzzle.
+#[derive(Clone, Debug)]
+pub struct CutTopology {
+    style: CutStyle,
+    num_cols: u32,
+    num_rows: u32,
+    width: f64,
+    height: f64,
+    seed: u64,
+    whimsies: Vec<PieceIndex>,
+}
+
+impl CutTopology {
+    pub fn new(
+        style: CutStyle,
+        num_cols: u32,
+        num_rows: u32,
+        width: u32,
+        height: u32,
+        seed: u64,
+        whimsies: Vec<PieceIndex>,
+    ) -> Self {
+        Self {
+            style,
+            num_cols,
+            num_rows,
+            width: width.into(),
+            height: height.into(),
+            seed,
+            whimsies,
+        }
+    }
+
+    // odd hexagon rows are shifted half a piece and get a half piece on both ends
+    fn row_len(&self, row: u32) -> u32 {
+        if self.style == CutStyle::Hexagonal && row % 2 == 1 {
+            self.num_cols + 1
+        } else {
+            self.num_cols
+        }
+    }
+
+    pub fn indices(&self) -> Vec<PieceIndex> {
+        (0..self.num_rows)
+            .flat_map(|row| (0..self.row_len(row)).map(move |col| PieceIndex(row, col)))
+            .filter(|index| {
+                // whimsies are named after the top left cell of their cluster
+                self.whimsy_at(i64::from(index.0), i64::from(index.1))
+                    .is_none_or(|whimsy| whimsy == *index)
+            })
+            .collect()
+    }
+
+    pub fn is_corner(&self, index: &PieceIndex) -> bool {
+        let PieceIndex(row, col) = *index;
+        (row == 0 || row == self.num_rows - 1) && (col == 0 || col == self.row_len(row) - 1)
+    }
+
+    pub fn is_edge(&self, index: &PieceIndex) -> bool {
+        let PieceIndex(row, col) = *index;
+        let border =
+            row == 0 || row == self.num_rows - 1 || col == 0 || col == self.row_len(row) - 1;
+        border && !self.is_corner(index)
+    }
+
+    fn cell_size(&self) -> (f64, f64) {
+        (
+            self.width / f64::from(self.num_cols),
+            self.height / f64::from(self.num_rows),
+        )
+    }
+
+    // rows of hexagons overlap by a quarter of their height, and the pointy tops of the
+    // first and last rows are cut off by the frame
+    fn hex_size(&self) -> (f64, f64) {
+        (
+            self.width / f64::from(self.num_cols),
+            self.height / (0.75 * f64::from(self.num_rows) - 0.25),
+        )
+    }
+
+    pub fn center(&self, index: &PieceIndex) -> (f64, f64) {
+        let PieceIndex(row, col) = *index;
+        if self.whimsies.contains(index) {
+            return self.grid_corner(i64::from(row) + 1, i64::from(col) + 1);
+        }
+
+        match self.style {
+            CutStyle::Grid => {
+                let (cell_width, cell_height) = self.cell_size();
+                (
+                    (f64::from(col) + 0.5) * cell_width,
+                    (f64::from(row) + 0.5) * cell_height,
+                )
+            }
+            CutStyle::Hexagonal => {
+                let (hex_width, hex_height) = self.hex_size();
+                let shift = if row % 2 == 1 { 0.0 } else { 0.5 };
+                (
+                    (f64::from(col) + shift) * hex_width,
+                    (0.25 + 0.75 * f64::from(row)) * hex_height,
+                )
+            }
+            CutStyle::Irregular => {
+                let corners = self.polygon(index);
+                let count = corners.len() as f64;
+                let x = corners.iter().map(|(corner, _)| corner.0).sum::<f64>() / count;
+                let y = corners.iter().map(|(corner, _)| corner.1).sum::<f64>() / count;
+                (x, y)
+            }
+        }
+    }
+
+    pub fn neighbors(&self, index: &PieceIndex) -> Vec<PieceIndex> {
+        self.polygon(index)
+            .into_iter()
+            .filter_map(|(_, neighbor)| neighbor)
+            .collect()
+    }
+
+    // bounding box of the piece without its tabs, limited to the frame
+    pub fn bounds(&self, index: &PieceIndex) -> (f64, f64, f64, f64) {
+        let (mut min_x, mut min_y) = (f64::INFINITY, f64::INFINITY);
+        let (mut max_x, mut max_y) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for ((x, y), _) in self.polygon(index) {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+        (
+            min_x.max(0.0),
+            min_y.max(0.0),
+            max_x.min(self.width),
+            max_y.min(self.height),
+        )
+    }
+
+    // corners of the piece going clockwise, each with the neighbor across the edge to the next corner
+    fn polygon(&self, index: &PieceIndex) -> Vec<((f64, f64), Option<PieceIndex>)> {
+        let PieceIndex(row, col) = *index;
+        let row = i64::from(row);
+        let col = i64::from(col);
+
+        if self.style == CutStyle::Hexagonal {
+            let (hex_width, hex_height) = self.hex_size();
+            let (x, y) = self.center(index);
+            let half_width = hex_width / 2.0;
+            return vec![
+                (
+                    (x, y - hex_height / 2.0),
+                    self.hex_at(row - 1, x + half_width),
+                ),
+                (
+                    (x + half_width, y - hex_height / 4.0),
+                    self.hex_at(row, x + hex_width),
+                ),
+                (
+                    (x + half_width, y + hex_height / 4.0),
+                    self.hex_at(row + 1, x + half_width),
+                ),
+                (
+                    (x, y + hex_height / 2.0),
+                    self.hex_at(row + 1, x - half_width),
+                ),
+                (
+                    (x - half_width, y + hex_height / 4.0),
+                    self.hex_at(row, x - hex_width),
+                ),
+                (
+                    (x - half_width, y - hex_height / 4.0),
+                    self.hex_at(row - 1, x - half_width),
+                ),
+            ];
+        }
+
+        if self.whimsies.contains(index) {
+            return vec![
+                (self.grid_corner(row, col), self.grid_at(row - 1, col)),
+                (
+                    self.grid_corner(row, col + 1),
+                    self.grid_at(row - 1, col + 1),
+                ),
+                (self.grid_corner(row, col + 2), self.grid_at(row, col + 2)),
+                (
+                    self.grid_corner(row + 1, col + 2),
+                    self.grid_at(row + 1, col + 2),
+                ),
+                (
+                    self.grid_corner(row + 2, col + 2),
+                    self.grid_at(row + 2, col + 1),
+                ),
+                (
+                    self.grid_corner(row + 2, col + 1),
+                    self.grid_at(row + 2, col),
+                ),
+                (
+                    self.grid_corner(row + 2, col),
+                    self.grid_at(row + 1, col - 1),
+                ),
+                (self.grid_corner(row + 1, col), self.grid_at(row, col - 1)),
+            ];
+        }
+
+        vec![
+            (self.grid_corner(row, col), self.grid_at(row - 1, col)),
+            (self.grid_corner(row, col + 1), self.grid_at(row, col + 1)),
+            (
+                self.grid_corner(row + 1, col + 1),
+                self.grid_at(row + 1, col),
+            ),
+            (self.grid_corner(row + 1, col), self.grid_at(row, col - 1)),
+        ]
+    }
+
+    fn grid_at(&self, row: i64, col: i64) -> Option<PieceIndex> {
+        let in_bounds = (0..i64::from(self.num_rows)).contains(&row)
+            && (0..i64::from(self.num_cols)).contains(&col);
+        in_bounds.then(|| {
+            self.whimsy_at(row, col)
+                .unwrap_or(PieceIndex(row as u32, col as u32))
+        })
+    }
+
+    pub fn whimsies(&self) -> &[PieceIndex] {
+        &self.whimsies
+    }
+
+    // the whimsy whose cluster covers this cell, if any
+    fn whimsy_at(&self, row: i64, col: i64) -> Option<PieceIndex> {
+        self.whimsies
+            .iter()
+            .copied()
+            .find(|PieceIndex(whimsy_row, whimsy_col)| {
+                (i64::from(*whimsy_row)..=i64::from(*whimsy_row) + 1).contains(&row)
+                    && (i64::from(*whimsy_col)..=i64::from(*whimsy_col) + 1).contains(&col)
+            })
+    }
+
+    // where the whimsy silhouette is drawn, as x, y, width and height in whole pixels
+    pub fn whimsy_box(&self, index: &PieceIndex) -> (u32, u32, u32, u32) {
+        let (center_x, center_y) = self.center(index);
+        let (cell_width, cell_height) = self.cell_size();
+        let half_width = WHIMSY_SIZE_RATIO * cell_width;
+        let half_height = WHIMSY_SIZE_RATIO * cell_height;
+        (
+            (center_x - half_width).floor() as u32,
+            (center_y - half_height).floor() as u32,
+            (2.0 * half_width) as u32,
+            (2.0 * half_height) as u32,
+        )
+    }
+
+    // the hexagon in a row whose center is at x, if there is one
+    fn hex_at(&self, row: i64, x: f64) -> Option<PieceIndex> {
+        if !(0..i64::from(self.num_rows)).contains(&row) {
+            return None;
+        }
+
+        let (hex_width, _) = self.hex_size();
+        let shift = if row % 2 == 1 { 0.0 } else { 0.5 };
+        let col = (x / hex_width - shift).round();
+        if col < 0.0 || col >= f64::from(self.row_len(row as u32)) {
+            return None;
+        }
+        Some(PieceIndex(row as u32, col as u32))
+    }
+
+    // corners of grid cells, jittered for irregular cuts. corners on the frame only slide
+    // along it so the border stays straight
+    fn grid_corner(&self, row: i64, col: i64) -> (f64, f64) {
+        let (cell_width, cell_height) = self.cell_size();
+        let mut x = col as f64 * cell_width;
+        let mut y = row as f64 * cell_height;
+
+        if self.style == CutStyle::Irregular {
+            let hash = self.hash(&[1, row as u64, col as u64]);
+            if col > 0 && col < i64::from(self.num_cols) {
+                x += (unit(hash) - 0.5) * 2.0 * IRREGULAR_JITTER * cell_width;
+            }
+            if row > 0 && row < i64::from(self.num_rows) {
+                y += (unit(hash >> 32) - 0.5) * 2.0 * IRREGULAR_JITTER * cell_height;
+            }
+        }
+
+        (x, y)
+    }
+
+    // the piece outline as a closed polyline, with curved tabs on every edge shared with
+    // another piece
+    pub fn outline(&self, index: &PieceIndex) -> Vec<(f64, f64)> {
+        let corners = self.polygon(index);
+        let mut points = Vec::new();
+
+        for (i, (start, neighbor)) in corners.iter().enumerate() {
+            let end = corners[(i + 1) % corners.len()].0;
+            match neighbor {
+                // pieces around a whimsy take a wedge of its cluster, the silhouette is cut
+                // out of them afterwards
+                Some(neighbor) if self.whimsies.contains(neighbor) => {
+                    points.push(*start);
+                    points.push(self.center(neighbor));
+                }
+                Some(neighbor) => points.extend(self.edge_curve(index, neighbor, *start, end)),
+                None => points.push(*start),
+            }
+        }
+
+        points
+    }
+
+    // points along the edge from start up to (but not including) end. both pieces sharing the
+    // edge must get the same curve, so it's always computed from the lower index's side.
+    fn edge_curve(
+        &self,
+        index: &PieceIndex,
+        neighbor: &PieceIndex,
+        start: (f64, f64),
+        end: (f64, f64),
+    ) -> Vec<(f64, f64)> {
+        let key = |index: &PieceIndex| (index.0, index.1);
+        let reversed = key(index) > key(neighbor);
+        let (first, second) = if reversed {
+            (neighbor, index)
+        } else {
+            (index, neighbor)
+        };
+        let (from, to) = if reversed { (end, start) } else { (start, end) };
+
+        let hash = self.hash(&[
+            2,
+            u64::from(first.0),
+            u64::from(first.1),
+            u64::from(second.0),
+            u64::from(second.1),
+        ]);
+        let jitter = |n: u64| (unit(self.hash(&[hash, n])) - 0.5) * 2.0 * CURVED_TAB_JITTER;
+        let (a, b, c, d, e) = (jitter(0), jitter(1), jitter(2), jitter(3), jitter(4));
+        let side = if hash & 1 == 0 { 1.0 } else { -1.0 };
+        let t = CURVED_TAB_SIZE;
+
+        // control points of three cubic curves, u along the edge and v across it
+        let control = [
+            (0.0, 0.0),
+            (0.2, a),
+            (0.5 + b + d, -t + c),
+            (0.5 - t + b, t + c),
+            (0.5 - 2.0 * t + b - d, 3.0 * t + c),
+            (0.5 + 2.0 * t + b - d, 3.0 * t + c),
+            (0.5 + t + b, t + c),
+            (0.5 + b + d, -t + c),
+            (0.8, e),
+            (1.0, 0.0),
+        ];
+
+        let dx = to.0 - from.0;
+        let dy = to.1 - from.1;
+        let to_image = |(u, v): (f64, f64)| {
+            let v = v * side;
+            (from.0 + u * dx - v * dy, from.1 + u * dy + v * dx)
+        };
+
+        let mut points = Vec::new();
+        for curve in control[..].windows(4).step_by(3) {
+            for step in 0..CURVE_SAMPLES {
+                let s = step as f64 / CURVE_SAMPLES as f64;
+                let r = 1.0 - s;
+                let weights = [r * r * r, 3.0 * r * r * s, 3.0 * r * s * s, s * s * s];
+                let u = (0..4).map(|i| weights[i] * curve[i].0).sum::<f64>();
+                let v = (0..4).map(|i| weights[i] * curve[i].1).sum::<f64>();
+                points.push(to_image((u, v)));
+            }
+        }
+        points.push(to);
+
+        if reversed {
+            points.reverse();
+        }
+        points.pop();
+        points
+    }
+
+    // splitmix64 over the cut seed and some values
+    fn hash(&self, values: &[u64]) -> u64 {
+        values.iter().fold(self.seed, |state, value| {
+            let mut z = (state ^ value).wrapping_add(0x9e3779b97f4a7c15);
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        })
+    }
+}
+
+fn unit(hash: u64) -> f64 {
+    (hash & 0xffff_ffff) as f64 / f64::from(u32::MAX)
+}
+
+// pick the top left cells of up to count 2x2 clusters, away from the frame and from each other
+pub(crate) fn place_whimsies(
+    num_cols: u32,
+    num_rows: u32,
+    count: usize,
+    rng: &mut impl Rng,
+) -> Vec<PieceIndex> {
+    let mut candidates = Vec::new();
+    for row in 1..num_rows.saturating_sub(2) {
+        for col in 1..num_cols.saturating_sub(2) {
+            candidates.push(PieceIndex(row, col));
+        }
+    }
+    candidates.shuffle(rng);
+
+    let mut whimsies: Vec<PieceIndex> = Vec::new();
+    for candidate in candidates {
+        if whimsies.len() == count {
+            break;
+        }
+        let apart = whimsies.iter().all(|whimsy| {
+            candidate.0.abs_diff(whimsy.0) >= 3 || candidate.1.abs_diff(whimsy.1) >= 3
+        });
+        if apart {
+            whimsies.push(candidate);
+        }
+    }
+    whimsies
+}
@@ -16,8 +16,10 @@ pub enum AnyGameEvent {
     PiecePutDown(PiecePutDownEvent),
     PieceConnectionCheck(PieceConnectionCheckEvent),
     PieceConnection(PieceConnectionEvent),
+    PieceFlipped(PieceFlippedEvent),
     PlayerCursorMoved(PlayerCursorMovedEvent),
     PlayerDisconnected(PlayerDisconnectedEvent),
+    PuzzleChanged(PuzzleChangedEvent),
 }
 
 impl AnyGameEvent {
@@ -33,6 +35,7 @@ impl AnyGameEvent {
         match self {
             AnyGameEvent::PiecePickedUp(ref mut event) => event.player_id = Some(id),
             AnyGameEvent::PiecePutDown(ref mut event) => event.player_id = Some(id),
+            AnyGameEvent::PieceFlipped(ref mut event) => event.player_id = Some(id),
             AnyGameEvent::PlayerCursorMoved(ref mut event) => event.player_id = Some(id),
             _ => (),
         }
@@ -110,6 +113,19 @@ impl GameEvent for PieceConnectionEvent {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Event)]
+pub struct PieceFlippedEvent {
+    pub player_id: Option<Uuid>,
+    pub index: PieceIndex,
+    pub flipped: bool,
+}
+
+impl GameEvent for PieceFlippedEvent {
+    fn serialize(&self) -> String {
+        AnyGameEvent::PieceFlipped(*self).serialize()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, Event)]
 pub struct PlayerCursorMovedEvent {
     pub player_id: Option<Uuid>,
@@ -132,3 +148,13 @@ impl GameEvent for PlayerDisconnectedEvent {
         AnyGameEvent::PlayerDisconnected(*self).serialize()
     }
 }
+
+// the server moved on to a new puzzle, which it sends right after this event
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Event)]
+pub struct PuzzleChangedEvent;
+
+impl GameEvent for PuzzleChangedEvent {
+    fn serialize(&self) -> String {
+        AnyGameEvent::PuzzleChanged(*self).serialize()
+    }
+}
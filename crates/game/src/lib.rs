@@ -3,11 +3,26 @@ pub use uuid::Uuid;
 
 pub mod image;
 
+pub mod cut;
+pub use cut::*;
+
 pub mod events;
 pub use events::*;
 
+pub mod options;
+pub use options::*;
+
 pub mod piece;
 pub use piece::*;
 
 pub mod puzzle;
 pub use puzzle::*;
+
+pub mod snapshot;
+pub use snapshot::SNAPSHOT_VERSION;
+
+pub mod recording;
+pub use recording::*;
+
+pub mod render;
+pub use render::{contact_sheet, PlacedPiece, RenderOptions, TableRenderer};
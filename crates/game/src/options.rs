@@ -0,0 +1,167 @@
+use std::{fmt::Debug, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::{Color, CutStyle, CONNECTION_DISTANCE_RATIO};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LockRule {
+    // groups lock into the frame when one of the four corner pieces is in place
+    #[default]
+    Corners,
+    // groups lock into the frame when any corner or edge piece is in place
+    Edges,
+    // groups lock into the frame when any of their pieces is in place
+    Any,
+}
+
+impl FromStr for LockRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "corners" => Ok(Self::Corners),
+            "edges" => Ok(Self::Edges),
+            "any" => Ok(Self::Any),
+            _ => Err(anyhow!("unknown lock rule: {s}")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Outline {
+    // the whole rectangular image is cut into pieces
+    #[default]
+    None,
+    // transparent parts of the puzzle image are left out
+    Alpha,
+    // a separate image whose transparent (or, without alpha, light) parts are left out
+    Silhouette(Bytes),
+}
+
+impl Outline {
+    pub fn is_none(&self) -> bool {
+        matches!(self, Self::None)
+    }
+}
+
+impl Debug for Outline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "None"),
+            Self::Alpha => write!(f, "Alpha"),
+            Self::Silhouette(bytes) => write!(f, "Silhouette({} bytes)", bytes.len()),
+        }
+    }
+}
+
+// an svg silhouette cut as a single oddly shaped piece
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Whimsy {
+    pub svg: Bytes,
+}
+
+impl Debug for Whimsy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Whimsy({} bytes)", self.svg.len())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PieceBack {
+    // single sided puzzle, pieces can't be flipped
+    #[default]
+    None,
+    Color(Color),
+    // stretched over the back of the whole puzzle
+    Image(Bytes),
+}
+
+impl Debug for PieceBack {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "None"),
+            Self::Color(color) => write!(f, "Color({color:?})"),
+            Self::Image(bytes) => write!(f, "Image({} bytes)", bytes.len()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StartFace {
+    #[default]
+    Up,
+    Down,
+    Random,
+}
+
+impl FromStr for StartFace {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "up" => Ok(Self::Up),
+            "down" => Ok(Self::Down),
+            "random" => Ok(Self::Random),
+            _ => Err(anyhow!("unknown start face: {s}")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PuzzleOptions {
+    pub lock_rule: LockRule,
+
+    // snap distances as a ratio of the smaller piece side length
+    pub connection_tolerance: f32,
+    pub lock_tolerance: f32,
+
+    // give border pieces tabs and blanks too, so there are no flat edges to sort out. grid
+    // cuts only
+    pub edgeless: bool,
+
+    // cut only the parts of the image inside this outline
+    pub outline: Outline,
+
+    pub cut_style: CutStyle,
+
+    // placed wherever there's room for them, not every whimsy is guaranteed to fit
+    pub whimsies: Vec<Whimsy>,
+
+    // pieces only connect face up, start_face only matters when there's a back
+    pub back: PieceBack,
+    pub start_face: StartFace,
+
+    // exact columns and rows, instead of working them out from the target piece count
+    pub grid: Option<(u32, u32)>,
+
+    // shown to players along with the full image
+    pub title: Option<String>,
+    pub attribution: Option<String>,
+}
+
+impl Default for PuzzleOptions {
+    fn default() -> Self {
+        Self {
+            lock_rule: LockRule::default(),
+            connection_tolerance: CONNECTION_DISTANCE_RATIO,
+            lock_tolerance: CONNECTION_DISTANCE_RATIO,
+            edgeless: false,
+            outline: Outline::None,
+            cut_style: CutStyle::Grid,
+            whimsies: Vec::new(),
+            back: PieceBack::None,
+            start_face: StartFace::Up,
+            grid: None,
+            title: None,
+            attribution: None,
+        }
+    }
+}
@@ -116,6 +116,35 @@ impl PieceKind {
         ]
     };
 
+    pub fn is_corner(&self) -> bool {
+        use PieceKind::*;
+        matches!(
+            self,
+            TopLeftCorner
+                | TopRightCornerEven
+                | TopRightCornerOdd
+                | BottomLeftCornerEven
+                | BottomLeftCornerOdd
+                | BottomRightCornerEven
+                | BottomRightCornerOdd
+        )
+    }
+
+    pub fn is_edge(&self) -> bool {
+        use PieceKind::*;
+        matches!(
+            self,
+            TopEdgeEven
+                | TopEdgeOdd
+                | BottomEdgeEven
+                | BottomEdgeOdd
+                | LeftEdgeEven
+                | LeftEdgeOdd
+                | RightEdgeEven
+                | RightEdgeOdd
+        )
+    }
+
     pub fn render_mask_and_shadow(&self, piece_width: u32, piece_height: u32) -> (Sprite, Sprite) {
         let (tab_width, tab_height) = Piece::tab_size(piece_width, piece_height);
         let (north_tab, south_tab, east_tab, west_tab) = self.tabs();
@@ -419,6 +448,16 @@ impl PieceKind {
         }
     }
 
+    // every piece gets tabs and blanks on all four sides so border pieces don't stand out
+    pub fn new_edgeless(index: &PieceIndex) -> Self {
+        let PieceIndex(row, col) = *index;
+        if (row + col) % 2 == 0 {
+            PieceKind::MiddleEven
+        } else {
+            PieceKind::MiddleOdd
+        }
+    }
+
     pub(crate) fn tabs(&self) -> (u32, u32, u32, u32) {
         use PieceKind::*;
 
@@ -492,11 +531,16 @@ pub struct Piece {
     kind: PieceKind,
     pub(crate) translation: Vec3,
     pub(crate) group_index: usize,
+    pub(crate) flipped: bool,
 }
 
 impl Piece {
     pub fn new(puzzle: &Puzzle, index: PieceIndex, group_index: usize) -> Self {
-        let kind = PieceKind::new(&index, puzzle.num_cols(), puzzle.num_rows());
+        let kind = if puzzle.options().edgeless {
+            PieceKind::new_edgeless(&index)
+        } else {
+            PieceKind::new(&index, puzzle.num_cols(), puzzle.num_rows())
+        };
         let padding = puzzle.piece_width().max(puzzle.piece_height()) / 2;
         let initial_position = bevy::prelude::Vec3::new(
             index.1 as f32 * (puzzle.piece_height() + padding) as f32,
@@ -509,9 +553,17 @@ impl Piece {
             kind,
             translation: initial_position,
             group_index,
+            flipped: false,
         }
     }
 
+    // room needed around the puzzle image for tabs that stick out past the border
+    pub(crate) fn edge_margin(piece_width: u32, piece_height: u32) -> (u32, u32) {
+        let (tab_width, tab_height) = Piece::tab_size(piece_width, piece_height);
+        let oversize = (piece_width.min(piece_height) / PIECE_OVERSIZE_DENOM).max(1);
+        (tab_width + oversize, tab_height + oversize)
+    }
+
     fn tab_size(piece_width: u32, piece_height: u32) -> (u32, u32) {
         let mut tab_width = (TAB_LENGTH_RATIO * f64::from(piece_width)) as u32;
         if tab_width % 2 == 1 {
@@ -534,6 +586,7 @@ impl Piece {
 
     pub fn cut_sprites(&self, puzzle: &Puzzle, image: &RgbaImage) -> (Sprite, Sprite) {
         let PieceIndex(row, col) = self.index;
+        let (margin_x, margin_y) = puzzle.image_margin();
         let piece_width = puzzle.piece_width();
         let piece_height = puzzle.piece_height();
         let (tab_width, tab_height) = Piece::tab_size(piece_width, piece_height);
@@ -573,8 +626,8 @@ impl Piece {
         let mut image = image.clone();
         let mut crop = image::imageops::crop(
             &mut image,
-            col * piece_width - tab_width * west_tab - w_oversize,
-            row * piece_height - tab_height * north_tab - n_oversize,
+            margin_x + col * piece_width - tab_width * west_tab - w_oversize,
+            margin_y + row * piece_height - tab_height * north_tab - n_oversize,
             sprite_width,
             sprite_height,
         )
@@ -732,8 +785,10 @@ impl Piece {
             mask.as_mut(),
         );
 
+        // keep the image's own transparency so outlines carry over to the piece
         for (x, y, pixel) in crop.enumerate_pixels_mut() {
-            pixel.channels_mut()[3] = mask.pixel(x, y).unwrap().alpha();
+            let alpha = pixel.channels()[3] as u16 * mask.pixel(x, y).unwrap().alpha() as u16;
+            pixel.channels_mut()[3] = (alpha / 255) as u8;
         }
 
         let sprite = Sprite {
@@ -804,7 +859,12 @@ impl Piece {
     }
 
     pub fn cut_mask_and_shadow(&self, puzzle: &Puzzle) -> (Sprite, Sprite, u32, u32) {
+        if !puzzle.uses_piece_kinds() {
+            return self.cut_outline_mask_and_shadow(puzzle);
+        }
+
         let PieceIndex(row, col) = self.index;
+        let (margin_x, margin_y) = puzzle.image_margin();
         let piece_width = puzzle.piece_width();
         let piece_height = puzzle.piece_height();
         let (tab_width, tab_height) = Piece::tab_size(piece_width, piece_height);
@@ -841,8 +901,8 @@ impl Piece {
         let sprite_origin_x: f64 = (piece_width / 2 + west_tab * tab_width + w_oversize).into();
         let sprite_origin_y: f64 = (piece_height / 2 + south_tab * tab_height + s_oversize).into();
 
-        let crop_x = col * piece_width - tab_width * west_tab - w_oversize;
-        let crop_y = row * piece_height - tab_height * north_tab - n_oversize;
+        let crop_x = margin_x + col * piece_width - tab_width * west_tab - w_oversize;
+        let crop_y = margin_y + row * piece_height - tab_height * north_tab - n_oversize;
 
         let n_oversize = n_oversize as f64;
         let s_oversize = s_oversize as f64;
@@ -1063,6 +1123,91 @@ impl Piece {
         (mask_sprite, shadow_sprite, crop_x, crop_y)
     }
 
+    // non-grid cuts give every piece its own shape, traced from the cut topology
+    fn cut_outline_mask_and_shadow(&self, puzzle: &Puzzle) -> (Sprite, Sprite, u32, u32) {
+        let topology = puzzle.topology();
+        let (margin_x, margin_y) = puzzle.image_margin();
+
+        if let Some(mask) = puzzle.whimsy_mask(&self.index) {
+            let (box_x, box_y, _, _) = topology.whimsy_box(&self.index);
+            let (mask_sprite, shadow_sprite) =
+                whimsy_mask_and_shadow(mask, puzzle.piece_width().min(puzzle.piece_height()));
+            return (
+                mask_sprite,
+                shadow_sprite,
+                box_x + margin_x,
+                box_y + margin_y,
+            );
+        }
+
+        let outline = topology.outline(&self.index);
+        let (center_x, center_y) = topology.center(&self.index);
+
+        // keep the sprite inside the image, which also cuts border pieces flat along the frame
+        let (mut min_x, mut min_y) = (f64::INFINITY, f64::INFINITY);
+        let (mut max_x, mut max_y) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for (x, y) in &outline {
+            min_x = min_x.min(*x);
+            min_y = min_y.min(*y);
+            max_x = max_x.max(*x);
+            max_y = max_y.max(*y);
+        }
+        let min_x = min_x.floor().max(-f64::from(margin_x));
+        let min_y = min_y.floor().max(-f64::from(margin_y));
+        let max_x = max_x.ceil().min(f64::from(puzzle.width() + margin_x));
+        let max_y = max_y.ceil().min(f64::from(puzzle.height() + margin_y));
+
+        let sprite_width = (max_x - min_x) as u32;
+        let sprite_height = (max_y - min_y) as u32;
+        let crop_x = (min_x + f64::from(margin_x)) as u32;
+        let crop_y = (min_y + f64::from(margin_y)) as u32;
+
+        let mut path_data = usvg::PathData::new();
+        for (i, (x, y)) in outline.iter().enumerate() {
+            if i == 0 {
+                path_data.push_move_to(x - min_x, y - min_y);
+            } else {
+                path_data.push_line_to(x - min_x, y - min_y);
+            }
+        }
+        path_data.push_close_path();
+
+        // whimsy silhouettes get cut out of the pieces around them
+        let cutouts = topology
+            .whimsies()
+            .iter()
+            .filter_map(|whimsy| {
+                let (box_x, box_y, box_width, box_height) = topology.whimsy_box(whimsy);
+                let overlaps = f64::from(box_x) < max_x
+                    && f64::from(box_x + box_width) > min_x
+                    && f64::from(box_y) < max_y
+                    && f64::from(box_y + box_height) > min_y;
+                if !overlaps {
+                    return None;
+                }
+
+                let mask = puzzle.whimsy_mask(whimsy)?;
+                Some((
+                    mask,
+                    box_x as i32 - min_x as i32,
+                    box_y as i32 - min_y as i32,
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        let (mask_sprite, shadow_sprite) = render_mask_and_shadow(
+            path_data,
+            &cutouts,
+            sprite_width,
+            sprite_height,
+            center_x - min_x,
+            max_y - center_y,
+            puzzle.piece_width().min(puzzle.piece_height()).into(),
+        );
+
+        (mask_sprite, shadow_sprite, crop_x, crop_y)
+    }
+
     pub fn index(&self) -> PieceIndex {
         self.index
     }
@@ -1073,6 +1218,7 @@ impl Piece {
 
     pub fn crop_offset(&self, puzzle: &Puzzle) -> (u32, u32) {
         let PieceIndex(row, col) = self.index;
+        let (margin_x, margin_y) = puzzle.image_margin();
         let piece_width = puzzle.piece_width();
         let piece_height = puzzle.piece_height();
         let (tab_width, tab_height) = Piece::tab_size(piece_width, piece_height);
@@ -1083,12 +1229,183 @@ impl Piece {
         let w_oversize = if west_tab + west_blank > 0 { oversize } else { 0 };
         let n_oversize = if north_tab + north_blank > 0 { oversize } else { 0 };
 
-        let crop_x = col * piece_width - tab_width * west_tab - w_oversize;
-        let crop_y = row * piece_height - tab_height * north_tab - n_oversize;
+        let crop_x = margin_x + col * piece_width - tab_width * west_tab - w_oversize;
+        let crop_y = margin_y + row * piece_height - tab_height * north_tab - n_oversize;
         (crop_x, crop_y)
     }
 
     pub fn translation(&self) -> Vec3 {
         self.translation
     }
+
+    pub fn flipped(&self) -> bool {
+        self.flipped
+    }
+}
+
+// fill the path for the mask, minus any cutouts, and stroke it for the shadow around it
+fn render_mask_and_shadow(
+    path_data: usvg::PathData,
+    cutouts: &[(tiny_skia::Pixmap, i32, i32)],
+    sprite_width: u32,
+    sprite_height: u32,
+    sprite_origin_x: f64,
+    sprite_origin_y: f64,
+    piece_size: f64,
+) -> (Sprite, Sprite) {
+    let tree_size = usvg::Size::new(sprite_width.into(), sprite_height.into()).unwrap();
+    let tree = usvg::Tree {
+        size: tree_size,
+        view_box: usvg::ViewBox {
+            rect: tree_size.to_rect(0.0, 0.0),
+            aspect: usvg::AspectRatio::default(),
+        },
+        root: usvg::Node::new(usvg::NodeKind::Group(usvg::Group::default())),
+    };
+
+    let mut shadow_path_data = path_data.clone();
+
+    tree.root.append_kind(usvg::NodeKind::Path(usvg::Path {
+        data: Rc::new(path_data),
+        fill: Some(usvg::Fill::default()), // black
+        ..usvg::Path::default()
+    }));
+
+    let mut mask = resvg::tiny_skia::Pixmap::new(sprite_width, sprite_height).unwrap();
+    resvg::render(
+        &tree,
+        usvg::FitTo::Original,
+        tiny_skia::Transform::default(),
+        mask.as_mut(),
+    );
+
+    for (cutout, x, y) in cutouts {
+        mask.draw_pixmap(
+            *x,
+            *y,
+            cutout.as_ref(),
+            &tiny_skia::PixmapPaint {
+                blend_mode: tiny_skia::BlendMode::DestinationOut,
+                ..tiny_skia::PixmapPaint::default()
+            },
+            tiny_skia::Transform::identity(),
+            None,
+        );
+    }
+
+    let mask_sprite = Sprite {
+        image: mask.into(),
+        origin_x: sprite_origin_x,
+        origin_y: sprite_origin_y,
+    };
+
+    let mut shadow_stroke_width = piece_size / SHADOW_STROKE_DENOM;
+    if shadow_stroke_width % 2.0 != 0.0 {
+        shadow_stroke_width += 2.0 - shadow_stroke_width % 2.0;
+    }
+
+    let shadow_tree_size = usvg::Size::new(
+        f64::from(sprite_width) + shadow_stroke_width,
+        f64::from(sprite_height) + shadow_stroke_width,
+    )
+    .unwrap();
+
+    let shadow_tree = usvg::Tree {
+        size: shadow_tree_size,
+        view_box: usvg::ViewBox {
+            rect: shadow_tree_size.to_rect(0.0, 0.0),
+            aspect: usvg::AspectRatio::default(),
+        },
+        root: usvg::Node::new(usvg::NodeKind::Group(usvg::Group::default())),
+    };
+
+    shadow_path_data.transform(usvg::Transform::new_translate(
+        shadow_stroke_width / 2.0,
+        shadow_stroke_width / 2.0,
+    ));
+
+    shadow_tree
+        .root
+        .append_kind(usvg::NodeKind::Path(usvg::Path {
+            data: Rc::new(shadow_path_data),
+            fill: Some(usvg::Fill::default()), // black
+            stroke: Some(usvg::Stroke {
+                width: usvg::StrokeWidth::new(shadow_stroke_width).unwrap(),
+                linecap: usvg::LineCap::Round,
+                linejoin: usvg::LineJoin::Round,
+                ..usvg::Stroke::default()
+            }),
+            ..usvg::Path::default()
+        }));
+
+    let mut shadow = resvg::tiny_skia::Pixmap::new(
+        sprite_width + shadow_stroke_width as u32,
+        sprite_height + shadow_stroke_width as u32,
+    )
+    .unwrap();
+
+    resvg::render(
+        &shadow_tree,
+        usvg::FitTo::Original,
+        tiny_skia::Transform::default(),
+        shadow.as_mut(),
+    );
+
+    let shadow_sprite = Sprite {
+        image: shadow.into(),
+        origin_x: sprite_origin_x + shadow_stroke_width / 2.0,
+        origin_y: sprite_origin_y + shadow_stroke_width / 2.0,
+    };
+
+    (mask_sprite, shadow_sprite)
+}
+
+// whimsies have no path to stroke, so their shadow is the silhouette stamped in a ring
+fn whimsy_mask_and_shadow(mask: tiny_skia::Pixmap, piece_size: u32) -> (Sprite, Sprite) {
+    let mut shadow_stroke_width = piece_size / SHADOW_STROKE_DENOM as u32;
+    shadow_stroke_width += shadow_stroke_width % 2;
+    let radius = shadow_stroke_width as f32 / 2.0;
+
+    let mut shadow = resvg::tiny_skia::Pixmap::new(
+        mask.width() + shadow_stroke_width,
+        mask.height() + shadow_stroke_width,
+    )
+    .unwrap();
+
+    for step in 0..16 {
+        let angle = step as f32 * std::f32::consts::TAU / 16.0;
+        shadow.draw_pixmap(
+            0,
+            0,
+            mask.as_ref(),
+            &tiny_skia::PixmapPaint::default(),
+            tiny_skia::Transform::from_translate(
+                radius + radius * angle.cos(),
+                radius + radius * angle.sin(),
+            ),
+            None,
+        );
+    }
+
+    // the silhouette can be any color, shadows are black
+    for pixel in shadow.pixels_mut() {
+        *pixel = tiny_skia::PremultipliedColorU8::from_rgba(0, 0, 0, pixel.alpha()).unwrap();
+    }
+
+    let origin_x = f64::from(mask.width()) / 2.0;
+    let origin_y = f64::from(mask.height()) / 2.0;
+
+    let shadow_sprite = Sprite {
+        image: shadow.into(),
+        origin_x: origin_x + f64::from(radius),
+        origin_y: origin_y + f64::from(radius),
+    };
+
+    let mask_sprite = Sprite {
+        image: mask.into(),
+        origin_x,
+        origin_y,
+    };
+
+    (mask_sprite, shadow_sprite)
 }
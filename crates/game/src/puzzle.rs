@@ -1,18 +1,24 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::OnceLock};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use bevy::{
     prelude::Vec3,
     utils::{HashMap, HashSet},
 };
 use bytes::Bytes;
-use image::{DynamicImage, RgbaImage};
+use image::{imageops::FilterType, DynamicImage, GrayImage, Luma, Rgba, RgbaImage};
 use rand::prelude::*;
+use resvg::{
+    tiny_skia::{self, Pixmap},
+    usvg,
+};
 use serde::{Deserialize, Serialize};
 use serde_json_any_key::*;
 
 use crate::{
-    AnyGameEvent, Color, Piece, PieceConnectionEvent, PieceIndex, PieceKind, PieceMovedEvent, Uuid,
+    cut::place_whimsies, snapshot, AnyGameEvent, Color, CutStyle, CutTopology, LockRule, Outline,
+    Piece, PieceBack, PieceConnectionEvent, PieceFlippedEvent, PieceIndex, PieceMovedEvent,
+    PuzzleOptions, StartFace, Uuid,
 };
 
 pub const CONNECTION_DISTANCE_RATIO: f32 = 0.2;
@@ -46,6 +52,17 @@ pub struct Puzzle {
     held_pieces: HashMap<Uuid, PieceIndex>,
 
     groups: Vec<Group>,
+
+    options: PuzzleOptions,
+
+    cut_seed: u64,
+
+    // where each of the whimsies in the options went
+    whimsies: Vec<PieceIndex>,
+
+    // built on first use and kept, nothing it depends on changes after the cut
+    #[serde(skip)]
+    topology: OnceLock<CutTopology>,
 }
 
 impl Debug for Puzzle {
@@ -58,6 +75,9 @@ impl Debug for Puzzle {
             .field("piece_map", &self.piece_map)
             .field("held_pieces", &self.held_pieces)
             .field("groups", &self.groups)
+            .field("options", &self.options)
+            .field("cut_seed", &self.cut_seed)
+            .field("whimsies", &self.whimsies)
             .finish()
     }
 }
@@ -67,31 +87,57 @@ impl Puzzle {
         raw_image: Bytes,
         target_piece_count: u32,
         randomize_position: bool,
+        options: PuzzleOptions,
     ) -> Result<Self> {
         let image = Self::image_from_bytes(&raw_image)?;
 
-        // compute puzzle width and height based while trying to make pieces as square as possible
-        let image_ratio = f64::from(image.width()) / f64::from(image.height());
-        let num_rows = (f64::from(target_piece_count) / image_ratio).sqrt();
-        let num_cols = image_ratio * num_rows;
+        if let Outline::Silhouette(silhouette) = &options.outline {
+            Self::silhouette_alpha(silhouette, image.width(), image.height())?;
+        }
 
-        let num_rows = num_rows.round().max(2.0) as u32;
-        let num_cols = num_cols.round().max(2.0) as u32;
+        if !options.whimsies.is_empty() && options.cut_style == CutStyle::Hexagonal {
+            bail!("whimsy pieces need a grid or irregular cut");
+        }
+        // only grid cuts give the border pieces outer tabs
+        if options.edgeless && options.cut_style != CutStyle::Grid {
+            bail!("edgeless puzzles need a grid cut");
+        }
+        for whimsy in &options.whimsies {
+            usvg::Tree::from_data(&whimsy.svg, &usvg::Options::default())?;
+        }
 
-        // make sure piece sizes are even so tabs are centered.
-        let mut piece_width = image.width() / num_cols;
-        if piece_width % 2 == 1 {
-            piece_width -= 1;
+        let (num_cols, num_rows) = match options.grid {
+            Some((num_cols, num_rows)) => (num_cols.max(2), num_rows.max(2)),
+            None => Self::grid_size(&image, target_piece_count, options.cut_style),
+        };
+
+        let (mut piece_width, mut piece_height) =
+            Self::piece_size(image.width(), image.height(), num_cols, num_rows);
+
+        if options.edgeless {
+            // shrink the grid to leave image around the border for the outer tabs
+            let (margin_x, margin_y) = Piece::edge_margin(piece_width, piece_height);
+            (piece_width, piece_height) = Self::piece_size(
+                image.width() - 2 * margin_x,
+                image.height() - 2 * margin_y,
+                num_cols,
+                num_rows,
+            );
         }
 
-        let mut piece_height = image.height() / num_rows;
-        if piece_height % 2 == 1 {
-            piece_height -= 1;
+        if piece_width == 0 || piece_height == 0 {
+            bail!(
+                "a {num_cols}x{num_rows} grid is too fine for a {}x{} image",
+                image.width(),
+                image.height()
+            );
         }
 
         let piece_map = HashMap::new();
         let held_pieces = HashMap::new();
         let groups = Vec::new();
+        let mut rng = rand::thread_rng();
+        let whimsies = place_whimsies(num_cols, num_rows, options.whimsies.len(), &mut rng);
 
         let mut puzzle = Self {
             raw_image,
@@ -102,65 +148,167 @@ impl Puzzle {
             piece_map,
             held_pieces,
             groups,
+            options,
+            cut_seed: rng.gen(),
+            whimsies,
+            topology: OnceLock::new(),
         };
 
-        let mut rng = rand::thread_rng();
-        let puzzle_width = puzzle.width() as f32;
-        let puzzle_height = puzzle.height() as f32;
-        let piece_big_side_len = piece_width.max(piece_height) as f32;
-        let short_side_len = puzzle_width.min(puzzle_height);
-        let long_side_len = puzzle_width.max(puzzle_height);
+        let outline = puzzle.outline_mask();
+        let (margin_x, margin_y) = puzzle.image_margin();
 
-        for row in 0..num_rows {
-            for col in 0..num_cols {
-                let index = PieceIndex(row, col);
-                let mut piece = Piece::new(&puzzle, index, puzzle.groups.len());
+        let topology = puzzle.topology().clone();
 
-                if randomize_position {
-                    let big_pos = (long_side_len + 2.0 * short_side_len) * (rng.gen::<f32>() - 0.5);
-                    let mut small_pos = 3.0 * short_side_len * (rng.gen::<f32>() - 0.5);
-                    if big_pos.abs() < long_side_len / 2.0 + piece_big_side_len
-                        && small_pos.abs() < short_side_len / 2.0 + piece_big_side_len
-                    {
-                        small_pos =
-                            (small_pos.abs() * 2.0 + short_side_len / 2.0 + piece_big_side_len)
-                                * small_pos.signum();
-                    }
-                    piece.translation = if puzzle_width >= puzzle_height {
-                        Vec3::new(big_pos, small_pos, 0.0)
-                    } else {
-                        Vec3::new(small_pos, big_pos, 0.0)
-                    };
+        for index in topology.indices() {
+            if let Some(outline) = &outline {
+                // cells completely outside the outline don't get a piece
+                let (min_x, min_y, max_x, max_y) = topology.bounds(&index);
+                let covered = (min_y as u32..max_y as u32).any(|y| {
+                    (min_x as u32..max_x as u32)
+                        .any(|x| outline.get_pixel(margin_x + x, margin_y + y)[0] > 0)
+                });
+                if !covered {
+                    continue;
                 }
+            }
 
-                puzzle.piece_map.insert(index, piece);
+            let mut piece = Piece::new(&puzzle, index, puzzle.groups.len());
 
-                let mut piece_indices = HashSet::new();
-                piece_indices.insert(index);
+            if puzzle.is_double_sided() {
+                piece.flipped = match puzzle.options.start_face {
+                    StartFace::Up => false,
+                    StartFace::Down => true,
+                    StartFace::Random => rng.gen(),
+                };
+            }
 
-                puzzle.groups.push(Group {
-                    piece_indices,
-                    locked: false,
-                });
+            if randomize_position {
+                piece.translation = puzzle.scatter_position(&mut rng);
             }
+
+            puzzle.piece_map.insert(index, piece);
+
+            let mut piece_indices = HashSet::new();
+            piece_indices.insert(index);
+
+            puzzle.groups.push(Group {
+                piece_indices,
+                locked: false,
+            });
+        }
+
+        if puzzle.piece_map.is_empty() {
+            bail!("puzzle outline doesn't cover any pieces");
         }
 
         Ok(puzzle)
     }
 
+    // a random spot around the board that stays clear of the puzzle itself
+    fn scatter_position(&self, rng: &mut impl Rng) -> Vec3 {
+        let puzzle_width = self.width() as f32;
+        let puzzle_height = self.height() as f32;
+        let piece_big_side_len = self.piece_width.max(self.piece_height) as f32;
+        let short_side_len = puzzle_width.min(puzzle_height);
+        let long_side_len = puzzle_width.max(puzzle_height);
+
+        let big_pos = (long_side_len + 2.0 * short_side_len) * (rng.gen::<f32>() - 0.5);
+        let mut small_pos = 3.0 * short_side_len * (rng.gen::<f32>() - 0.5);
+        if big_pos.abs() < long_side_len / 2.0 + piece_big_side_len
+            && small_pos.abs() < short_side_len / 2.0 + piece_big_side_len
+        {
+            small_pos = (small_pos.abs() * 2.0 + short_side_len / 2.0 + piece_big_side_len)
+                * small_pos.signum();
+        }
+        if puzzle_width >= puzzle_height {
+            Vec3::new(big_pos, small_pos, 0.0)
+        } else {
+            Vec3::new(small_pos, big_pos, 0.0)
+        }
+    }
+
+    // half the width and height of the area scatter_position picks from, around the origin
+    pub fn scatter_extent(&self) -> (f32, f32) {
+        let puzzle_width = self.width() as f32;
+        let puzzle_height = self.height() as f32;
+        let piece_big_side_len = self.piece_width.max(self.piece_height) as f32;
+        let short_side_len = puzzle_width.min(puzzle_height);
+        let long_side_len = puzzle_width.max(puzzle_height);
+
+        let big_extent = long_side_len / 2.0 + short_side_len;
+        let small_extent = 1.5 * short_side_len + 3.0 * piece_big_side_len;
+        if puzzle_width >= puzzle_height {
+            (big_extent, small_extent)
+        } else {
+            (small_extent, big_extent)
+        }
+    }
+
+    // move every piece that isn't connected, locked or held back out around the board
+    pub fn scatter(&mut self) -> Vec<PieceMovedEvent> {
+        let mut rng = rand::thread_rng();
+        let loose_pieces: Vec<_> = self
+            .groups
+            .iter()
+            .filter(|group| !group.locked && group.piece_indices.len() == 1)
+            .flat_map(|group| group.piece_indices.iter().copied())
+            .filter(|index| !self.piece_held(index))
+            .collect();
+
+        let mut events = Vec::new();
+        for index in loose_pieces {
+            let target = self.scatter_position(&mut rng);
+            events.extend(self.move_piece(&index, target.x, target.y));
+        }
+        events
+    }
+
+    fn grid_size(image: &DynamicImage, target_piece_count: u32, cut_style: CutStyle) -> (u32, u32) {
+        // compute puzzle width and height based while trying to make pieces as square as possible
+        let image_ratio = f64::from(image.width()) / f64::from(image.height());
+        let num_rows = (f64::from(target_piece_count) / image_ratio).sqrt();
+        let num_cols = image_ratio * num_rows;
+
+        let mut num_rows = num_rows.round().max(2.0) as u32;
+        let num_cols = num_cols.round().max(2.0) as u32;
+
+        if cut_style == CutStyle::Hexagonal {
+            // hexagon rows overlap, so it takes more of them to keep pieces about as tall as wide
+            num_rows = (f64::from(num_rows) * 2.0 / 3f64.sqrt() + 1.0 / 3.0).round() as u32;
+        }
+
+        (num_cols, num_rows)
+    }
+
+    fn piece_size(width: u32, height: u32, num_cols: u32, num_rows: u32) -> (u32, u32) {
+        // make sure piece sizes are even so tabs are centered.
+        let mut piece_width = width / num_cols;
+        if piece_width % 2 == 1 {
+            piece_width -= 1;
+        }
+
+        let mut piece_height = height / num_rows;
+        if piece_height % 2 == 1 {
+            piece_height -= 1;
+        }
+
+        (piece_width, piece_height)
+    }
+
+    // versioned so older backups can be migrated, see snapshot.rs
     pub fn serialize(&self) -> String {
-        serde_json::to_string(self).unwrap()
+        snapshot::serialize(self)
     }
 
     pub fn serialize_without_image(&self) -> String {
-        serde_json::to_string(self).unwrap()
+        snapshot::serialize(self)
     }
 
     pub fn deserialize(value: &str) -> Result<Self> {
-        serde_json::from_str(value).map_err(anyhow::Error::from)
+        snapshot::deserialize(value)
     }
 
-    fn image_from_bytes(bytes: &Bytes) -> Result<DynamicImage> {
+    pub fn image_from_bytes(bytes: &Bytes) -> Result<DynamicImage> {
         image::load_from_memory(bytes.as_ref())
             .or_else(|_| {
                 image::load_from_memory_with_format(bytes.as_ref(), image::ImageFormat::Jpeg)
@@ -171,20 +319,69 @@ impl Puzzle {
     pub fn rgba_image(&self) -> RgbaImage {
         // crop pixels from right and bottom of image to make size multiple of piece size
         let mut image = Self::image_from_bytes(&self.raw_image).unwrap();
-        image::imageops::crop(
-            &mut image,
-            0,
-            0,
-            self.num_cols * self.piece_width,
-            self.num_rows * self.piece_height,
-        )
-        .to_image()
+        let silhouette = match &self.options.outline {
+            Outline::Silhouette(silhouette) => {
+                Some(Self::silhouette_alpha(silhouette, image.width(), image.height()).unwrap())
+            }
+            _ => None,
+        };
+
+        let mut image =
+            image::imageops::crop(&mut image, 0, 0, self.image_width(), self.image_height())
+                .to_image();
+
+        if let Some(silhouette) = silhouette {
+            for (x, y, pixel) in image.enumerate_pixels_mut() {
+                let alpha = silhouette.get_pixel(x, y)[0];
+                pixel[3] = (pixel[3] as u16 * alpha as u16 / 255) as u8;
+            }
+        }
+
+        image
+    }
+
+    // silhouette alpha scaled to the size of the puzzle image
+    fn silhouette_alpha(silhouette: &Bytes, width: u32, height: u32) -> Result<GrayImage> {
+        let silhouette = Self::image_from_bytes(silhouette)?;
+        let alpha = if silhouette.color().has_alpha() {
+            let rgba = silhouette.to_rgba8();
+            GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+                Luma([rgba.get_pixel(x, y)[3]])
+            })
+        } else {
+            // opaque silhouettes are dark shapes on a light background
+            let mut luma = silhouette.to_luma8();
+            image::imageops::invert(&mut luma);
+            luma
+        };
+        Ok(image::imageops::resize(
+            &alpha,
+            width,
+            height,
+            FilterType::Triangle,
+        ))
+    }
+
+    // alpha of the puzzle outline over the whole puzzle image, if it isn't a plain rectangle
+    pub fn outline_mask(&self) -> Option<GrayImage> {
+        if self.options.outline.is_none() {
+            return None;
+        }
+
+        let image = self.rgba_image();
+        Some(GrayImage::from_fn(image.width(), image.height(), |x, y| {
+            Luma([image.get_pixel(x, y)[3]])
+        }))
     }
 
     pub fn raw_image(&self) -> &Bytes {
         &self.raw_image
     }
 
+    pub fn options(&self) -> &PuzzleOptions {
+        &self.options
+    }
+
     pub fn num_cols(&self) -> u32 {
         self.num_cols
     }
@@ -197,6 +394,22 @@ impl Puzzle {
         self.piece_map.get(index)
     }
 
+    // indices of every piece that exists, in row order
+    pub fn piece_indices(&self) -> Vec<PieceIndex> {
+        let mut indices = self.piece_map.keys().copied().collect::<Vec<_>>();
+        indices.sort_by_key(|PieceIndex(row, col)| (*row, *col));
+        indices
+    }
+
+    // neighbors in the cut that actually have a piece, outlines can leave holes
+    pub fn neighbors(&self, index: &PieceIndex) -> Vec<PieceIndex> {
+        self.topology()
+            .neighbors(index)
+            .into_iter()
+            .filter(|other| self.piece_map.contains_key(other))
+            .collect()
+    }
+
     fn piece_mut(&mut self, index: &PieceIndex) -> Option<&mut Piece> {
         self.piece_map.get_mut(index)
     }
@@ -236,6 +449,100 @@ impl Puzzle {
         self.piece_height
     }
 
+    // border of extra image around the puzzle for the tabs of edge pieces in edgeless mode
+    pub fn image_margin(&self) -> (u32, u32) {
+        if self.options.edgeless {
+            Piece::edge_margin(self.piece_width, self.piece_height)
+        } else {
+            (0, 0)
+        }
+    }
+
+    pub fn image_width(&self) -> u32 {
+        self.width() + 2 * self.image_margin().0
+    }
+
+    pub fn image_height(&self) -> u32 {
+        self.height() + 2 * self.image_margin().1
+    }
+
+    // the back of the puzzle laid out like the front image, if pieces have a back
+    pub fn back_rgba_image(&self) -> Option<RgbaImage> {
+        let width = self.image_width();
+        let height = self.image_height();
+
+        let mut image = match &self.options.back {
+            PieceBack::None => return None,
+            PieceBack::Color(color) => RgbaImage::from_pixel(1, 1, Rgba(color.as_rgba_u8())),
+            PieceBack::Image(bytes) => Self::image_from_bytes(bytes)
+                .ok()?
+                .resize_exact(width, height, FilterType::Triangle)
+                .to_rgba8(),
+        };
+        if image.dimensions() != (width, height) {
+            image = image::imageops::resize(&image, width, height, FilterType::Nearest);
+        }
+
+        if let Some(outline) = self.outline_mask() {
+            for (x, y, pixel) in image.enumerate_pixels_mut() {
+                let alpha = outline.get_pixel(x, y)[0];
+                pixel[3] = (pixel[3] as u16 * alpha as u16 / 255) as u8;
+            }
+        }
+
+        Some(image)
+    }
+
+    pub fn topology(&self) -> &CutTopology {
+        self.topology.get_or_init(|| {
+            CutTopology::new(
+                self.options.cut_style,
+                self.num_cols,
+                self.num_rows,
+                self.width(),
+                self.height(),
+                self.cut_seed,
+                self.whimsies.clone(),
+            )
+        })
+    }
+
+    // whether every piece is cut to one of the shared piece kind shapes
+    pub fn uses_piece_kinds(&self) -> bool {
+        self.options.cut_style == CutStyle::Grid && self.whimsies.is_empty()
+    }
+
+    // the whimsy's silhouette rendered to the size of its box in the topology
+    pub fn whimsy_mask(&self, index: &PieceIndex) -> Option<Pixmap> {
+        let position = self.whimsies.iter().position(|whimsy| whimsy == index)?;
+        let svg = &self.options.whimsies[position].svg;
+        let tree = usvg::Tree::from_data(svg, &usvg::Options::default()).ok()?;
+        let (_, _, width, height) = self.topology().whimsy_box(index);
+
+        // fit the silhouette in the box without stretching it, centered
+        let scale =
+            (f64::from(width) / tree.size.width()).min(f64::from(height) / tree.size.height());
+        let offset_x = (f64::from(width) - tree.size.width() * scale) / 2.0;
+        let offset_y = (f64::from(height) - tree.size.height() * scale) / 2.0;
+
+        let mut mask = Pixmap::new(width, height)?;
+        resvg::render(
+            &tree,
+            usvg::FitTo::Zoom(scale as f32),
+            tiny_skia::Transform::from_translate(offset_x as f32, offset_y as f32),
+            mask.as_mut(),
+        )?;
+        Some(mask)
+    }
+
+    pub fn connection_distance(&self) -> f32 {
+        self.options.connection_tolerance * self.piece_width.min(self.piece_height) as f32
+    }
+
+    pub fn lock_distance(&self) -> f32 {
+        self.options.lock_tolerance * self.piece_width.min(self.piece_height) as f32
+    }
+
     pub fn width(&self) -> u32 {
         self.num_cols * self.piece_width
     }
@@ -330,22 +637,10 @@ impl Puzzle {
             return false;
         }
 
-        let neighbors: Vec<_> = index
-            .neighbors(self.num_cols, self.num_rows)
-            .into_iter()
-            .filter(|other| {
-                self.piece(index).unwrap().group_index != self.piece(other).unwrap().group_index
-            })
-            .collect();
-
-        let mut connection_count = 0;
-        let connection_dist =
-            CONNECTION_DISTANCE_RATIO * self.piece_width.min(self.piece_height) as f32;
-        let closest = neighbors
+        let candidates = self.connection_candidates(index);
+        let connection_count = candidates.len();
+        let closest = candidates
             .into_iter()
-            .map(|other| self.single_connection_check(index, &other))
-            .filter(|(_, distance, _)| *distance <= connection_dist)
-            .inspect(|_| connection_count += 1)
             .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
 
         if let Some(closest) = closest {
@@ -373,18 +668,58 @@ impl Puzzle {
         }
     }
 
+    fn connection_candidates(&self, index: &PieceIndex) -> Vec<(Vec3, f32, PieceIndex)> {
+        let group_index = self.piece(index).unwrap().group_index;
+        let connection_dist = self.connection_distance();
+
+        // only pieces that are both face up can connect
+        if self.piece(index).unwrap().flipped {
+            return Vec::new();
+        }
+
+        self.neighbors(index)
+            .into_iter()
+            .filter(|other| {
+                let other = self.piece(other).unwrap();
+                group_index != other.group_index && !other.flipped
+            })
+            .map(|other| self.single_connection_check(index, &other))
+            .filter(|(_, distance, _)| *distance <= connection_dist)
+            .collect()
+    }
+
+    // find the piece in the group that would connect if the group was put down right now,
+    // along with the translation it would snap to
+    pub fn connection_preview(&self, index: &PieceIndex) -> Option<(PieceIndex, Vec3)> {
+        if self.piece_group_locked(index) {
+            return None;
+        }
+
+        let group_index = self.piece(index)?.group_index;
+        self.groups[group_index]
+            .piece_indices
+            .iter()
+            .flat_map(|index| {
+                self.connection_candidates(index)
+                    .into_iter()
+                    .map(|(perfect, distance, _)| (*index, perfect, distance))
+            })
+            .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(index, perfect, _)| (index, perfect))
+    }
+
     fn single_connection_check(
-        &mut self,
+        &self,
         index: &PieceIndex,
         other: &PieceIndex,
     ) -> (Vec3, f32, PieceIndex) {
         let piece = self.piece(index).unwrap();
         let other_piece = self.piece(other).unwrap();
 
+        let offset = self.home_translation(index) - self.home_translation(other);
         let perfect = Vec3::new(
-            other_piece.translation.x + (index.1 as f32 - other.1 as f32) * self.piece_width as f32,
-            other_piece.translation.y
-                + (other.0 as f32 - index.0 as f32) * self.piece_height as f32,
+            other_piece.translation.x + offset.x,
+            other_piece.translation.y + offset.y,
             0.0,
         );
 
@@ -394,6 +729,11 @@ impl Puzzle {
     }
 
     fn group_lock_check(&mut self, index: &PieceIndex) -> bool {
+        // without flat edges there's no frame to lock into
+        if self.options.edgeless {
+            return false;
+        }
+
         let mut piece_indices = Vec::new();
         let group_index = self.piece(index).unwrap().group_index;
         self.with_group_mut(group_index, |piece| piece_indices.push(piece.index()));
@@ -403,65 +743,46 @@ impl Puzzle {
     }
 
     fn piece_lock_check(&mut self, index: &PieceIndex) -> bool {
-        use PieceKind::*;
-        let kind = PieceKind::new(index, self.num_cols, self.num_rows);
-        if matches!(
-            kind,
-            TopLeftCorner
-                | TopRightCornerOdd
-                | TopRightCornerEven
-                | BottomLeftCornerOdd
-                | BottomLeftCornerEven
-                | BottomRightCornerOdd
-                | BottomRightCornerEven
-        ) {
-            let translation = self.piece(index).unwrap().translation;
-
-            let half_width = self.num_cols as f32 * self.piece_width as f32 / 2.0;
-            let half_height = self.num_rows as f32 * self.piece_height as f32 / 2.0;
-
-            let half_piece_width = self.piece_width as f32 / 2.0;
-            let half_piece_height = self.piece_height as f32 / 2.0;
-
-            let target_x = match kind {
-                TopLeftCorner | BottomLeftCornerOdd | BottomLeftCornerEven => {
-                    -half_width + half_piece_width
-                }
-                TopRightCornerOdd
-                | TopRightCornerEven
-                | BottomRightCornerOdd
-                | BottomRightCornerEven => half_width - half_piece_width,
-                _ => 0.0,
-            };
-
-            let target_y = match kind {
-                TopLeftCorner | TopRightCornerOdd | TopRightCornerEven => {
-                    half_height - half_piece_height
-                }
-                BottomRightCornerOdd
-                | BottomRightCornerEven
-                | BottomLeftCornerOdd
-                | BottomLeftCornerEven => -half_height + half_piece_height,
-                _ => 0.0,
-            };
-
-            let x_dist = (translation.x - target_x).abs();
-            let y_dist = (translation.y - target_y).abs();
-            let square_dist = x_dist * x_dist + y_dist * y_dist;
-            let connection_dist =
-                CONNECTION_DISTANCE_RATIO * self.piece_width.min(self.piece_height) as f32;
-            if square_dist <= connection_dist * connection_dist {
-                self.move_piece_rel(
-                    index,
-                    Vec3::new(target_x - translation.x, target_y - translation.y, 0.0),
-                );
-                self.lock_piece_group(index);
-                return true;
-            }
+        let topology = self.topology();
+        let lockable = match self.options.lock_rule {
+            LockRule::Corners => topology.is_corner(index),
+            LockRule::Edges => topology.is_corner(index) || topology.is_edge(index),
+            LockRule::Any => true,
+        };
+
+        if !lockable || self.piece(index).unwrap().flipped {
+            return false;
+        }
+
+        let translation = self.piece(index).unwrap().translation;
+        let target = self.home_translation(index);
+
+        let x_dist = (translation.x - target.x).abs();
+        let y_dist = (translation.y - target.y).abs();
+        let square_dist = x_dist * x_dist + y_dist * y_dist;
+        let lock_dist = self.lock_distance();
+        if square_dist <= lock_dist * lock_dist {
+            self.move_piece_rel(
+                index,
+                Vec3::new(target.x - translation.x, target.y - translation.y, 0.0),
+            );
+            self.lock_piece_group(index);
+            return true;
         }
+
         false
     }
 
+    // where a piece sits in the frame when the puzzle is solved
+    fn home_translation(&self, index: &PieceIndex) -> Vec3 {
+        let (x, y) = self.topology().center(index);
+
+        let half_width = self.width() as f32 / 2.0;
+        let half_height = self.height() as f32 / 2.0;
+
+        Vec3::new(-half_width + x as f32, half_height - y as f32, 0.0)
+    }
+
     fn lock_piece_group(&mut self, index: &PieceIndex) {
         let group_index = self.piece(index).unwrap().group_index;
         self.groups[group_index].locked = true;
@@ -478,12 +799,73 @@ impl Puzzle {
             .any(|held_index| held_index == index)
     }
 
+    // how many pieces are in each group, leaving out the empty ones left behind by merges
+    pub fn group_sizes(&self) -> Vec<usize> {
+        self.groups
+            .iter()
+            .map(|group| group.piece_indices.len())
+            .filter(|&size| size > 0)
+            .collect()
+    }
+
     pub fn can_pick_up(&self, index: &PieceIndex) -> bool {
         !self.piece_group_locked(index) && !self.piece_held(index)
     }
 
+    pub fn is_double_sided(&self) -> bool {
+        !matches!(self.options.back, PieceBack::None)
+    }
+
+    // only loose pieces can be turned over, connected groups stay face up
+    pub fn can_flip(&self, index: &PieceIndex) -> bool {
+        let Some(piece) = self.piece(index) else {
+            return false;
+        };
+        let group = &self.groups[piece.group_index];
+        self.is_double_sided() && !group.locked && group.piece_indices.len() == 1
+    }
+
+    pub fn flip_piece(&mut self, index: &PieceIndex) -> Option<PieceFlippedEvent> {
+        if !self.can_flip(index) {
+            return None;
+        }
+
+        let piece = self.piece_mut(index).unwrap();
+        piece.flipped = !piece.flipped;
+        Some(PieceFlippedEvent {
+            player_id: None,
+            index: *index,
+            flipped: piece.flipped,
+        })
+    }
+
+    fn references_missing_piece(&self, event: &AnyGameEvent) -> bool {
+        use AnyGameEvent::*;
+        match event {
+            PieceMoved(event) => self.piece(&event.index).is_none(),
+            PiecePickedUp(event) => self.piece(&event.index).is_none(),
+            PiecePutDown(event) => self.piece(&event.index).is_none(),
+            PieceConnectionCheck(event) => self.piece(&event.index).is_none(),
+            PieceFlipped(event) => self.piece(&event.index).is_none(),
+            PieceConnection(event) => {
+                event.group_index >= self.groups.len()
+                    || event
+                        .piece_movements
+                        .iter()
+                        .any(|movement| self.piece(&movement.index).is_none())
+            }
+            PlayerCursorMoved(_) | PlayerDisconnected(_) | PuzzleChanged(_) => false,
+        }
+    }
+
     pub fn apply_event(&mut self, event: AnyGameEvent) -> Vec<AnyGameEvent> {
         use AnyGameEvent::*;
+
+        // clients can send indices of grid cells that have no piece
+        if self.references_missing_piece(&event) {
+            return Vec::new();
+        }
+
         match event {
             PieceMoved(event) => self
                 .try_move_piece(&event.index, event.x, event.y)
@@ -537,6 +919,18 @@ impl Puzzle {
                 self.groups[event.group_index].locked = event.locked;
                 event.piece_movements.into_iter().map(PieceMoved).collect()
             }
+            PieceFlipped(event) => {
+                // players can only flip the piece they're holding
+                let holding = event
+                    .player_id
+                    .is_none_or(|player_id| self.held_pieces.get(&player_id) == Some(&event.index));
+                if holding && self.can_flip(&event.index) {
+                    self.piece_mut(&event.index).unwrap().flipped = event.flipped;
+                    vec![PieceFlipped(event)]
+                } else {
+                    Vec::new()
+                }
+            }
             PlayerCursorMoved(event) => {
                 vec![PlayerCursorMoved(event)]
             }
@@ -544,16 +938,86 @@ impl Puzzle {
                 self.held_pieces.remove(&event.player_id);
                 vec![PlayerDisconnected(event)]
             }
+            // replacing the puzzle is up to whoever owns it
+            PuzzleChanged(_) => Vec::new(),
         }
     }
 
     pub fn piece_count(&self) -> u32 {
-        self.num_rows * self.num_cols
+        self.piece_map.len() as u32
+    }
+
+    // progress from 0 to 100 by the same measure as is_complete
+    pub fn percent_complete(&self) -> f32 {
+        let piece_count = self.piece_map.len();
+        let locked_count: usize = self
+            .groups
+            .iter()
+            .filter(|group| group.locked)
+            .map(|group| group.piece_indices.len())
+            .sum();
+        let group_count = self
+            .groups
+            .iter()
+            .filter(|group| !group.piece_indices.is_empty())
+            .count();
+
+        let locked = locked_count as f32 / piece_count as f32;
+        let connected = if piece_count > 1 {
+            (piece_count - group_count) as f32 / (piece_count - 1) as f32
+        } else {
+            1.0
+        };
+
+        let fraction = if self.options.edgeless {
+            connected
+        } else if !self.options.outline.is_none() {
+            connected.max(locked)
+        } else {
+            locked
+        };
+        100.0 * fraction
     }
 
     pub fn is_complete(&self) -> bool {
-        self.groups
+        let single_group = self
+            .groups
             .iter()
-            .all(|group| group.locked || group.piece_indices.is_empty())
+            .any(|group| group.piece_indices.len() == self.piece_map.len());
+        let all_locked = self
+            .groups
+            .iter()
+            .all(|group| group.locked || group.piece_indices.is_empty());
+
+        if self.options.edgeless {
+            // nothing ever locks, so the puzzle is done once every piece is in one group
+            single_group
+        } else if !self.options.outline.is_none() {
+            // outlines can leave groups without a corner or edge to lock into the frame
+            single_group || all_locked
+        } else {
+            all_locked
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V0_SNAPSHOT: &str = include_str!("../tests/fixtures/snapshot_v0.json");
+
+    #[test]
+    fn connection_preview_targets_held_piece() {
+        let mut puzzle = Puzzle::deserialize(V0_SNAPSHOT).unwrap();
+        let held = PieceIndex(1, 1);
+        let neighbor = PieceIndex(1, 0);
+
+        // just short of where the held piece would sit beside its loose neighbor
+        let perfect = puzzle.piece(&neighbor).unwrap().translation + puzzle.home_translation(&held)
+            - puzzle.home_translation(&neighbor);
+        puzzle.piece_mut(&held).unwrap().translation = perfect + Vec3::new(0.1, 0.1, 0.0);
+
+        assert_eq!(puzzle.connection_preview(&held), Some((held, perfect)));
     }
 }
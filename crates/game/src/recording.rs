@@ -0,0 +1,58 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+use crate::{AnyGameEvent, Puzzle};
+
+// a stretch of play that can be watched back: the puzzle as it was when the recording starts
+// and every event applied to it after that, in order
+pub struct Recording {
+    // kept as the snapshot json so the starting point can be loaded again when rewinding
+    pub puzzle: String,
+    pub events: Vec<RecordedEvent>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordedEvent {
+    // since the start of the recording
+    pub millis: u64,
+    pub event: AnyGameEvent,
+}
+
+#[derive(Deserialize)]
+struct RecordingJson<'a> {
+    #[serde(borrow)]
+    puzzle: &'a RawValue,
+    events: Vec<RecordedEvent>,
+}
+
+impl Recording {
+    pub fn new(puzzle: &Puzzle, events: Vec<RecordedEvent>) -> Self {
+        Self {
+            puzzle: puzzle.serialize(),
+            events,
+        }
+    }
+
+    // the puzzle goes in as is, so it keeps its snapshot version
+    pub fn serialize(&self) -> String {
+        let events = serde_json::to_string(&self.events).unwrap();
+        format!("{{\"puzzle\":{},\"events\":{events}}}", self.puzzle)
+    }
+
+    pub fn deserialize(value: &str) -> Result<Self> {
+        let RecordingJson { puzzle, events } = serde_json::from_str(value)?;
+        Ok(Self {
+            puzzle: puzzle.get().to_string(),
+            events,
+        })
+    }
+
+    pub fn initial_puzzle(&self) -> Result<Puzzle> {
+        Puzzle::deserialize(&self.puzzle)
+    }
+
+    pub fn duration_millis(&self) -> u64 {
+        self.events.last().map_or(0, |event| event.millis)
+    }
+}
@@ -0,0 +1,392 @@
+use bevy::utils::HashMap;
+use image::{Rgba, RgbaImage};
+use resvg::tiny_skia::{
+    self, ColorU8, FilterQuality, Paint, PathBuilder, Pixmap, PixmapPaint, PremultipliedColorU8,
+    Rect, Stroke, Transform,
+};
+
+use crate::{PieceIndex, Puzzle};
+
+// same as the client's background and board
+const TABLE_COLOR: [u8; 3] = [54, 57, 62];
+const BOARD_COLOR: [u8; 3] = [66, 69, 73];
+
+const LOCKED_COLOR: [u8; 3] = [80, 200, 120];
+const UNLOCKED_COLOR: [u8; 3] = [220, 90, 80];
+const TINT_ALPHA: u8 = 100;
+const GROUP_OUTLINE_COLOR: [u8; 3] = [240, 200, 60];
+const GROUP_OUTLINE_WIDTH: f32 = 3.0;
+
+// space around each piece on a contact sheet, in puzzle pixels
+const SHEET_PADDING: u32 = 16;
+
+// extras for looking into the state of a puzzle, off for a plain picture of the table
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderOptions {
+    // a box around every group of connected pieces
+    pub group_outlines: bool,
+    // tint pieces green if they're locked in place and red if not
+    pub lock_colors: bool,
+}
+
+// a piece as it lay on the table at some point, see TableRenderer::placed_pieces
+#[derive(Clone, Copy, Debug)]
+pub struct PlacedPiece {
+    pub index: PieceIndex,
+    pub x: f32,
+    pub y: f32,
+    pub flipped: bool,
+    pub locked: bool,
+}
+
+struct PieceSprite {
+    front: Pixmap,
+    back: Option<Pixmap>,
+    // from the left and bottom of the sprite, like the client's meshes
+    origin_x: f32,
+    origin_y: f32,
+}
+
+// draws the table the way players see it, without a gpu. pieces are cut once up front so
+// the same puzzle can be drawn over and over as it changes
+pub struct TableRenderer {
+    sprites: HashMap<PieceIndex, PieceSprite>,
+    board: Pixmap,
+    // world position of the canvas's top left corner
+    left: f32,
+    top: f32,
+    scale: f32,
+    width: u32,
+    height: u32,
+}
+
+impl TableRenderer {
+    // the canvas covers the scatter area and wherever the pieces are now, scaled down so
+    // neither side is over max_size
+    pub fn new(puzzle: &Puzzle, max_size: u32) -> Self {
+        Self::with_extent(puzzle, Self::extent(puzzle), max_size)
+    }
+
+    // how far from the middle of the table the scatter area and the pieces reach, each way
+    pub fn extent(puzzle: &Puzzle) -> (f32, f32) {
+        let (mut extent_x, mut extent_y) = puzzle.scatter_extent();
+        for translation in puzzle.with_pieces(|piece| piece.translation) {
+            extent_x = extent_x.max(translation.x.abs());
+            extent_y = extent_y.max(translation.y.abs());
+        }
+        (extent_x, extent_y)
+    }
+
+    // for a canvas that has to fit more than the puzzle's current state, see extent
+    pub fn with_extent(puzzle: &Puzzle, (extent_x, extent_y): (f32, f32), max_size: u32) -> Self {
+        let margin = puzzle.piece_width().max(puzzle.piece_height()) as f32;
+        let extent_x = extent_x + margin;
+        let extent_y = extent_y + margin;
+
+        let scale = (max_size as f32 / (2.0 * extent_x.max(extent_y))).min(1.0);
+        let width = (2.0 * extent_x * scale).ceil() as u32;
+        let height = (2.0 * extent_y * scale).ceil() as u32;
+
+        let front = puzzle.rgba_image();
+        let back = puzzle.back_rgba_image();
+        let sprites = puzzle
+            .with_pieces(|piece| {
+                let (mask, _, crop_x, crop_y) = piece.cut_mask_and_shadow(puzzle);
+                let origin_x = mask.origin_x as f32;
+                let origin_y = mask.origin_y as f32;
+                let mask: RgbaImage = mask.image.into();
+                let sprite = PieceSprite {
+                    front: masked_pixmap(&front, &mask, crop_x, crop_y),
+                    back: back
+                        .as_ref()
+                        .map(|back| masked_pixmap(back, &mask, crop_x, crop_y)),
+                    origin_x,
+                    origin_y,
+                };
+                (piece.index(), sprite)
+            })
+            .into_iter()
+            .collect();
+
+        let board = match puzzle.outline_mask() {
+            Some(outline) => {
+                let [r, g, b] = BOARD_COLOR;
+                let board = RgbaImage::from_fn(outline.width(), outline.height(), |x, y| {
+                    Rgba([r, g, b, outline.get_pixel(x, y)[0]])
+                });
+                to_pixmap(&board)
+            }
+            None => {
+                let [r, g, b] = BOARD_COLOR;
+                let mut board = Pixmap::new(puzzle.width(), puzzle.height()).unwrap();
+                board.fill(tiny_skia::Color::from_rgba8(r, g, b, 255));
+                board
+            }
+        };
+
+        Self {
+            sprites,
+            board,
+            left: -extent_x,
+            top: extent_y,
+            scale,
+            width,
+            height,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn render(&self, puzzle: &Puzzle, options: &RenderOptions) -> RgbaImage {
+        let mut canvas = self.draw_table(&Self::placed_pieces(puzzle), options);
+
+        if options.group_outlines {
+            self.draw_group_outlines(&mut canvas, puzzle);
+        }
+
+        from_pixmap(&canvas)
+    }
+
+    // everything render needs to draw the pieces, for keeping many states of a puzzle around.
+    // locked pieces go on the bottom and held ones on top, like in the client
+    pub fn placed_pieces(puzzle: &Puzzle) -> Vec<PlacedPiece> {
+        let mut indices = puzzle.piece_indices();
+        indices.sort_by_key(|index| (!puzzle.piece_group_locked(index), puzzle.piece_held(index)));
+        indices
+            .into_iter()
+            .map(|index| {
+                let piece = puzzle.piece(&index).unwrap();
+                PlacedPiece {
+                    index,
+                    x: piece.translation.x,
+                    y: piece.translation.y,
+                    flipped: piece.flipped,
+                    locked: puzzle.piece_group_locked(&index),
+                }
+            })
+            .collect()
+    }
+
+    // like render, but without the group outlines since those need the whole puzzle
+    pub fn render_pieces(&self, pieces: &[PlacedPiece], options: &RenderOptions) -> RgbaImage {
+        from_pixmap(&self.draw_table(pieces, options))
+    }
+
+    fn draw_table(&self, pieces: &[PlacedPiece], options: &RenderOptions) -> Pixmap {
+        let mut canvas = Pixmap::new(self.width, self.height).unwrap();
+        let [r, g, b] = TABLE_COLOR;
+        canvas.fill(tiny_skia::Color::from_rgba8(r, g, b, 255));
+
+        // the board is centered on the origin
+        self.draw(
+            &mut canvas,
+            self.board.as_ref(),
+            -(self.board.width() as f32) / 2.0,
+            self.board.height() as f32 / 2.0,
+        );
+
+        for piece in pieces {
+            let sprite = &self.sprites[&piece.index];
+            let pixmap = match (&sprite.back, piece.flipped) {
+                (Some(back), true) => back,
+                _ => &sprite.front,
+            };
+            self.draw(
+                &mut canvas,
+                pixmap.as_ref(),
+                piece.x - sprite.origin_x,
+                piece.y - sprite.origin_y + pixmap.height() as f32,
+            );
+
+            if options.lock_colors {
+                let color = match piece.locked {
+                    true => LOCKED_COLOR,
+                    false => UNLOCKED_COLOR,
+                };
+                self.draw(
+                    &mut canvas,
+                    tinted(pixmap, color).as_ref(),
+                    piece.x - sprite.origin_x,
+                    piece.y - sprite.origin_y + pixmap.height() as f32,
+                );
+            }
+        }
+        canvas
+    }
+
+    fn draw_group_outlines(&self, canvas: &mut Pixmap, puzzle: &Puzzle) {
+        let half_width = puzzle.piece_width() as f32 / 2.0;
+        let half_height = puzzle.piece_height() as f32 / 2.0;
+
+        // world bounds of each group's pieces, and how many pieces it has
+        let mut groups: HashMap<usize, (Rect, usize)> = HashMap::new();
+        for (group_index, translation) in
+            puzzle.with_pieces(|piece| (piece.group_index, piece.translation))
+        {
+            let Some(piece_rect) = Rect::from_ltrb(
+                translation.x - half_width,
+                translation.y - half_height,
+                translation.x + half_width,
+                translation.y + half_height,
+            ) else {
+                continue;
+            };
+            let (rect, count) = groups.entry(group_index).or_insert((piece_rect, 0));
+            *rect = Rect::from_ltrb(
+                rect.left().min(piece_rect.left()),
+                rect.top().min(piece_rect.top()),
+                rect.right().max(piece_rect.right()),
+                rect.bottom().max(piece_rect.bottom()),
+            )
+            .unwrap();
+            *count += 1;
+        }
+
+        let mut paint = Paint::default();
+        let [r, g, b] = GROUP_OUTLINE_COLOR;
+        paint.set_color_rgba8(r, g, b, 255);
+        paint.anti_alias = true;
+        let stroke = Stroke {
+            width: GROUP_OUTLINE_WIDTH,
+            ..Stroke::default()
+        };
+
+        for (rect, count) in groups.into_values() {
+            if count < 2 {
+                continue;
+            }
+            // world y goes up and canvas y goes down
+            let Some(canvas_rect) = Rect::from_ltrb(
+                (rect.left() - self.left) * self.scale,
+                (self.top - rect.bottom()) * self.scale,
+                (rect.right() - self.left) * self.scale,
+                (self.top - rect.top()) * self.scale,
+            ) else {
+                continue;
+            };
+            let path = PathBuilder::from_rect(canvas_rect);
+            canvas.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+        }
+    }
+
+    // draw with the top left corner at a world position
+    fn draw(&self, canvas: &mut Pixmap, pixmap: tiny_skia::PixmapRef, x: f32, y: f32) {
+        let paint = PixmapPaint {
+            quality: FilterQuality::Bilinear,
+            ..PixmapPaint::default()
+        };
+        let transform = Transform::from_row(
+            self.scale,
+            0.0,
+            0.0,
+            self.scale,
+            (x - self.left) * self.scale,
+            (self.top - y) * self.scale,
+        );
+        canvas.draw_pixmap(0, 0, pixmap, &paint, transform, None);
+    }
+}
+
+// every piece cut out and laid out in rows, to check a cut before anyone plays it. scaled
+// down so neither side is over max_size
+pub fn contact_sheet(puzzle: &Puzzle, max_size: u32) -> RgbaImage {
+    let image = puzzle.rgba_image();
+    let mut indices = puzzle.piece_indices();
+    indices.sort_by_key(|index| (index.0, index.1));
+
+    // cut_sprites only knows the tabbed grid shapes, other cuts go through their masks
+    // like the table does
+    let sprites: Vec<Pixmap> = indices
+        .iter()
+        .map(|index| {
+            let piece = puzzle.piece(index).unwrap();
+            if puzzle.uses_piece_kinds() {
+                let (sprite, _) = piece.cut_sprites(puzzle, &image);
+                to_pixmap(&sprite.image.into())
+            } else {
+                let (mask, _, crop_x, crop_y) = piece.cut_mask_and_shadow(puzzle);
+                masked_pixmap(&image, &mask.image.into(), crop_x, crop_y)
+            }
+        })
+        .collect();
+
+    let cell_width = sprites.iter().map(Pixmap::width).max().unwrap_or(0) + SHEET_PADDING;
+    let cell_height = sprites.iter().map(Pixmap::height).max().unwrap_or(0) + SHEET_PADDING;
+    let columns = puzzle.num_cols().clamp(1, sprites.len().max(1) as u32);
+    let rows = (sprites.len() as u32).div_ceil(columns).max(1);
+    let full_width = (columns * cell_width + SHEET_PADDING) as f32;
+    let full_height = (rows * cell_height + SHEET_PADDING) as f32;
+
+    let scale = (max_size as f32 / full_width.max(full_height)).min(1.0);
+    let mut sheet = Pixmap::new(
+        (full_width * scale).ceil() as u32,
+        (full_height * scale).ceil() as u32,
+    )
+    .unwrap();
+    let [r, g, b] = TABLE_COLOR;
+    sheet.fill(tiny_skia::Color::from_rgba8(r, g, b, 255));
+
+    let paint = PixmapPaint {
+        quality: FilterQuality::Bilinear,
+        ..PixmapPaint::default()
+    };
+    for (i, sprite) in sprites.iter().enumerate() {
+        let (column, row) = (i as u32 % columns, i as u32 / columns);
+        // centered in its cell
+        let x = SHEET_PADDING + column * cell_width + (cell_width - sprite.width()) / 2;
+        let y = SHEET_PADDING + row * cell_height + (cell_height - sprite.height()) / 2;
+        let transform =
+            Transform::from_row(scale, 0.0, 0.0, scale, x as f32 * scale, y as f32 * scale);
+        sheet.draw_pixmap(0, 0, sprite.as_ref(), &paint, transform, None);
+    }
+
+    from_pixmap(&sheet)
+}
+
+// the part of the image under a piece's mask, which starts at crop_x, crop_y in the image
+fn masked_pixmap(image: &RgbaImage, mask: &RgbaImage, crop_x: u32, crop_y: u32) -> Pixmap {
+    let sprite = RgbaImage::from_fn(mask.width(), mask.height(), |x, y| {
+        let (image_x, image_y) = (crop_x + x, crop_y + y);
+        if image_x >= image.width() || image_y >= image.height() {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let mut pixel = *image.get_pixel(image_x, image_y);
+        pixel[3] = (pixel[3] as u16 * mask.get_pixel(x, y)[3] as u16 / 255) as u8;
+        pixel
+    });
+    to_pixmap(&sprite)
+}
+
+// a flat color in the shape of the pixmap, to lay over it
+fn tinted(pixmap: &Pixmap, [r, g, b]: [u8; 3]) -> Pixmap {
+    let mut tint = Pixmap::new(pixmap.width(), pixmap.height()).unwrap();
+    for (pixel, source) in tint.pixels_mut().iter_mut().zip(pixmap.pixels()) {
+        let alpha = (source.alpha() as u16 * TINT_ALPHA as u16 / 255) as u8;
+        *pixel = ColorU8::from_rgba(r, g, b, alpha).premultiply();
+    }
+    tint
+}
+
+fn to_pixmap(image: &RgbaImage) -> Pixmap {
+    let mut pixmap = Pixmap::new(image.width(), image.height()).unwrap();
+    for (pixel, &Rgba([r, g, b, a])) in pixmap.pixels_mut().iter_mut().zip(image.pixels()) {
+        *pixel = ColorU8::from_rgba(r, g, b, a).premultiply();
+    }
+    pixmap
+}
+
+fn from_pixmap(pixmap: &Pixmap) -> RgbaImage {
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .map(PremultipliedColorU8::demultiply)
+        .flat_map(|color| [color.red(), color.green(), color.blue(), color.alpha()])
+        .collect();
+    RgbaImage::from_raw(pixmap.width(), pixmap.height(), pixels).unwrap()
+}
@@ -0,0 +1,126 @@
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{Puzzle, PuzzleOptions};
+
+// bump this and add a migration whenever a change to Puzzle, Piece or anything inside them
+// would stop older backups from loading
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+type Migration = fn(&mut Map<String, Value>) -> Result<()>;
+
+// MIGRATIONS[n] upgrades a version n snapshot to version n + 1
+const MIGRATIONS: [Migration; SNAPSHOT_VERSION as usize] = [v0_to_v1];
+
+#[derive(Deserialize)]
+struct SnapshotVersion {
+    // snapshots from before versioning don't have one
+    #[serde(default)]
+    version: u32,
+}
+
+// the version goes in as the first field of the puzzle's own object
+pub(crate) fn serialize(puzzle: &Puzzle) -> String {
+    let json = serde_json::to_string(puzzle).unwrap();
+    let fields = json.strip_prefix('{').unwrap();
+    format!("{{\"version\":{SNAPSHOT_VERSION},{fields}")
+}
+
+pub(crate) fn deserialize(json: &str) -> Result<Puzzle> {
+    let SnapshotVersion { version } = serde_json::from_str(json)?;
+    if version == SNAPSHOT_VERSION {
+        return serde_json::from_str(json).map_err(anyhow::Error::from);
+    }
+    if version > SNAPSHOT_VERSION {
+        bail!("snapshot version {version} is newer than the supported version {SNAPSHOT_VERSION}");
+    }
+
+    let mut snapshot: Map<String, Value> = serde_json::from_str(json)?;
+    for (from_version, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(&mut snapshot)
+            .map_err(|e| anyhow!("error migrating snapshot from version {from_version}: {e}"))?;
+    }
+    serde_json::from_value(Value::Object(snapshot)).map_err(anyhow::Error::from)
+}
+
+// unversioned snapshots may be from before puzzle options, cut seeds, whimsies or flipping
+// existed
+fn v0_to_v1(snapshot: &mut Map<String, Value>) -> Result<()> {
+    if !snapshot.contains_key("options") {
+        snapshot.insert(
+            "options".to_string(),
+            serde_json::to_value(PuzzleOptions::default())?,
+        );
+    }
+    snapshot.entry("cut_seed").or_insert(0.into());
+    snapshot
+        .entry("whimsies")
+        .or_insert(Value::Array(Vec::new()));
+
+    let pieces = snapshot
+        .get_mut("piece_map")
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| anyhow!("missing piece_map"))?;
+    for piece in pieces.values_mut() {
+        piece
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("piece isn't an object"))?
+            .entry("flipped")
+            .or_insert(false.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::PieceIndex;
+
+    // a backup from before versioning, options, cut seeds, whimsies and flipping
+    const V0_SNAPSHOT: &str = include_str!("../tests/fixtures/snapshot_v0.json");
+
+    #[test]
+    fn migrates_v0_snapshot() {
+        let puzzle = Puzzle::deserialize(V0_SNAPSHOT).unwrap();
+
+        assert_eq!(puzzle.piece_count(), 4);
+        assert_eq!(puzzle.group_sizes(), vec![2, 1, 1]);
+        assert!(puzzle.piece_group_locked(&PieceIndex(0, 1)));
+        assert!(puzzle.piece_held(&PieceIndex(1, 1)));
+        assert!(puzzle
+            .with_pieces(|piece| piece.flipped)
+            .iter()
+            .all(|flipped| !flipped));
+
+        assert_eq!(
+            serde_json::to_string(puzzle.options()).unwrap(),
+            serde_json::to_string(&PuzzleOptions::default()).unwrap()
+        );
+
+        let migrated: Value = serde_json::from_str(&puzzle.serialize()).unwrap();
+        assert_eq!(migrated["version"], json!(SNAPSHOT_VERSION));
+        assert_eq!(migrated["cut_seed"], json!(0));
+        assert_eq!(migrated["whimsies"], json!([]));
+    }
+
+    #[test]
+    fn loads_current_version() {
+        let json = Puzzle::deserialize(V0_SNAPSHOT).unwrap().serialize();
+        assert_eq!(Puzzle::deserialize(&json).unwrap().serialize(), json);
+    }
+
+    #[test]
+    fn rejects_future_version() {
+        let mut snapshot: Value =
+            serde_json::from_str(&Puzzle::deserialize(V0_SNAPSHOT).unwrap().serialize()).unwrap();
+        snapshot["version"] = json!(SNAPSHOT_VERSION + 1);
+
+        let error = Puzzle::deserialize(&snapshot.to_string()).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("newer than the supported version"));
+    }
+}
@@ -0,0 +1,426 @@
+use std::{
+    convert::Infallible,
+    fs::{read_to_string, write},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use log::{info, warn};
+use serde::Deserialize;
+use tokio::task::spawn_blocking;
+use toml_edit::{value, Array, Document};
+use uuid::Uuid;
+use warp::{
+    http::StatusCode,
+    reject::Reject,
+    reply::{json, with_header, with_status, Response},
+    Filter, Rejection, Reply,
+};
+
+use game::{RecordedEvent, Recording};
+
+use crate::{
+    clients::IpDenylist,
+    rooms::{Rooms, DEFAULT_ROOM},
+    storage::Storage,
+    upload::upload_handler,
+    Config, CONFIG_FILE,
+};
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl Reject for Unauthorized {}
+
+#[derive(Deserialize)]
+struct NewRoom {
+    id: String,
+}
+
+// GET    /admin/rooms                  status of every running room
+// POST   /admin/rooms                  create an empty room from a json {"id": <room id>}
+// GET    /admin/rooms/<room id>        status of one room
+// DELETE /admin/rooms/<room id>        close a room and remove everything stored for it
+// POST   /admin/rooms/<room id>/skip   move on to the next puzzle in the queue
+// POST   /admin/rooms/<room id>/scatter
+// POST   /admin/rooms/<room id>/backup
+// POST   /admin/rooms/<room id>/kick/<player id>
+// POST   /admin/rooms/<room id>/upload multipart image and options, see upload_handler
+// GET    /admin/rooms/<room id>/events events journaled since the last backup
+// GET    /admin/rooms/<room id>/completed history of finished puzzles
+// GET    /admin/rooms/<room id>/recording the puzzle's first backup and events since, for
+//                                      /?replay=<url>, which asks for the admin token
+// GET    /admin/denylist
+// PUT    /admin/denylist               replace the ip denylist with a json list, which is
+//                                      saved to the config file too
+//
+// every request needs an "Authorization: Bearer <admin_token>" header, and without an
+// admin_token in the config, or with a blank one, the api is disabled
+pub fn admin_routes(
+    rooms: Rooms,
+    ip_denylist: IpDenylist,
+    config: Arc<Config>,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    let admin_token = config.admin_token.clone().filter(|token| {
+        let blank = token.trim().is_empty();
+        if blank {
+            warn!("admin_token is blank, the admin api is disabled");
+        }
+        !blank
+    });
+    let max_upload_size = config.max_upload_size;
+    let rooms = warp::any().map(move || rooms.clone());
+    let ip_denylist = warp::any().map(move || ip_denylist.clone());
+
+    let all_status = warp::path!("rooms")
+        .and(warp::get())
+        .and(rooms.clone())
+        .and_then(all_status);
+
+    let create_room = warp::path!("rooms")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rooms.clone())
+        .and_then(create_room);
+
+    let room_status = warp::path!("rooms" / String)
+        .and(warp::get())
+        .and(rooms.clone())
+        .and_then(room_status);
+
+    let close_room = warp::path!("rooms" / String)
+        .and(warp::delete())
+        .and(rooms.clone())
+        .and_then(close_room);
+
+    let skip = warp::path!("rooms" / String / "skip")
+        .and(warp::post())
+        .and(rooms.clone())
+        .and_then(skip);
+
+    let scatter = warp::path!("rooms" / String / "scatter")
+        .and(warp::post())
+        .and(rooms.clone())
+        .and_then(scatter);
+
+    let backup = warp::path!("rooms" / String / "backup")
+        .and(warp::post())
+        .and(rooms.clone())
+        .and_then(backup);
+
+    let kick = warp::path!("rooms" / String / "kick" / Uuid)
+        .and(warp::post())
+        .and(rooms.clone())
+        .and_then(kick);
+
+    let upload = warp::path!("rooms" / String / "upload")
+        .and(warp::post())
+        .and(warp::multipart::form().max_length(max_upload_size))
+        .and(rooms.clone())
+        .and(warp::any().map(move || config.clone()))
+        .and_then(upload_handler);
+
+    let events = warp::path!("rooms" / String / "events")
+        .and(warp::get())
+        .and(rooms.clone())
+        .and_then(events);
+
+    let completed = warp::path!("rooms" / String / "completed")
+        .and(warp::get())
+        .and(rooms.clone())
+        .and_then(completed);
+
+    let recording = warp::path!("rooms" / String / "recording")
+        .and(warp::get())
+        .and(rooms.clone())
+        .and_then(recording);
+
+    let get_denylist = warp::path!("denylist")
+        .and(warp::get())
+        .and(ip_denylist.clone())
+        .and_then(get_denylist);
+
+    let set_denylist = warp::path!("denylist")
+        .and(warp::put())
+        .and(warp::body::json())
+        .and(ip_denylist)
+        .and(rooms)
+        .and_then(set_denylist);
+
+    warp::path("admin")
+        .and(authorized(admin_token))
+        .and(
+            all_status
+                .or(create_room)
+                .unify()
+                .or(room_status)
+                .unify()
+                .or(close_room)
+                .unify()
+                .or(skip)
+                .unify()
+                .or(scatter)
+                .unify()
+                .or(backup)
+                .unify()
+                .or(kick)
+                .unify()
+                .or(upload)
+                .unify()
+                .or(events)
+                .unify()
+                .or(completed)
+                .unify()
+                .or(recording)
+                .unify()
+                .or(get_denylist)
+                .unify()
+                .or(set_denylist)
+                .unify(),
+        )
+        .recover(handle_rejection)
+        .unify()
+}
+
+fn authorized(admin_token: Option<String>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let admin_token = admin_token.clone();
+            async move {
+                let token = header
+                    .as_deref()
+                    .and_then(|header| header.strip_prefix("Bearer "));
+                match (admin_token, token) {
+                    (Some(admin_token), Some(token)) if tokens_match(&admin_token, token) => Ok(()),
+                    _ => Err(warp::reject::custom(Unauthorized)),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+// compare every byte so the time taken doesn't give away how much of the token was right
+fn tokens_match(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .bytes()
+            .zip(actual.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn handle_rejection(rejection: Rejection) -> Result<Response, Rejection> {
+    if rejection.find::<Unauthorized>().is_some() {
+        Ok(StatusCode::UNAUTHORIZED.into_response())
+    } else {
+        Err(rejection)
+    }
+}
+
+async fn all_status(rooms: Rooms) -> Result<Response, Infallible> {
+    let mut statuses = Vec::new();
+    for room in rooms.all().await {
+        statuses.push(room.status().await);
+    }
+    Ok(json(&statuses).into_response())
+}
+
+async fn room_status(room_id: String, rooms: Rooms) -> Result<Response, Infallible> {
+    Ok(match rooms.get(&room_id).await {
+        Some(room) => json(&room.status().await).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    })
+}
+
+async fn create_room(new_room: NewRoom, rooms: Rooms) -> Result<Response, Infallible> {
+    let room_id = new_room.id;
+    if !Rooms::is_valid_id(&room_id) {
+        return Ok(with_status("invalid room id", StatusCode::BAD_REQUEST).into_response());
+    }
+
+    Ok(match rooms.create(&room_id).await {
+        Ok(true) => StatusCode::CREATED.into_response(),
+        Ok(false) => with_status("room already exists", StatusCode::CONFLICT).into_response(),
+        Err(e) => with_status(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    })
+}
+
+async fn close_room(room_id: String, rooms: Rooms) -> Result<Response, Infallible> {
+    if room_id == DEFAULT_ROOM {
+        return Ok(
+            with_status("the default room can't be closed", StatusCode::CONFLICT).into_response(),
+        );
+    }
+    if !rooms.exists(&room_id) {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    info!("admin closing room {room_id}");
+    Ok(match rooms.close(&room_id).await {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => with_status(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    })
+}
+
+async fn skip(room_id: String, rooms: Rooms) -> Result<Response, Infallible> {
+    let Some(room) = rooms.get(&room_id).await else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    info!("admin skipping puzzle in room {room_id}");
+    Ok(if room.next_puzzle().await {
+        StatusCode::OK.into_response()
+    } else {
+        with_status("image queue is empty", StatusCode::CONFLICT).into_response()
+    })
+}
+
+async fn scatter(room_id: String, rooms: Rooms) -> Result<Response, Infallible> {
+    let Some(room) = rooms.get(&room_id).await else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    info!("admin scattering pieces in room {room_id}");
+    room.scatter().await;
+    Ok(StatusCode::OK.into_response())
+}
+
+async fn backup(room_id: String, rooms: Rooms) -> Result<Response, Infallible> {
+    let Some(room) = rooms.get(&room_id).await else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(match room.backup().await {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => with_status(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    })
+}
+
+async fn kick(room_id: String, player_id: Uuid, rooms: Rooms) -> Result<Response, Infallible> {
+    let Some(room) = rooms.get(&room_id).await else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(if room.kick(&player_id).await {
+        info!("admin kicked client {player_id} from room {room_id}");
+        StatusCode::OK.into_response()
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    })
+}
+
+async fn events(room_id: String, rooms: Rooms) -> Result<Response, Infallible> {
+    if !rooms.exists(&room_id) {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let storage = rooms.storage();
+    Ok(
+        match spawn_blocking(move || storage.events(&room_id))
+            .await
+            .unwrap()
+        {
+            Ok(events) => json(&events).into_response(),
+            Err(e) => with_status(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+        },
+    )
+}
+
+async fn completed(room_id: String, rooms: Rooms) -> Result<Response, Infallible> {
+    if !rooms.exists(&room_id) {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let storage = rooms.storage();
+    Ok(
+        match spawn_blocking(move || storage.completed_puzzles(&room_id))
+            .await
+            .unwrap()
+        {
+            Ok(completed) => json(&completed).into_response(),
+            Err(e) => with_status(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+        },
+    )
+}
+
+async fn recording(room_id: String, rooms: Rooms) -> Result<Response, Infallible> {
+    if !rooms.exists(&room_id) {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let storage = rooms.storage();
+    let res = spawn_blocking(move || load_recording(&*storage, &room_id))
+        .await
+        .unwrap();
+
+    Ok(match res {
+        Ok(Some(recording)) => {
+            with_header(recording.serialize(), "content-type", "application/json").into_response()
+        }
+        Ok(None) => with_status("room has no recording", StatusCode::NOT_FOUND).into_response(),
+        Err(e) => with_status(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    })
+}
+
+// the room's current or last puzzle from its first snapshot on, out of the journal archive
+pub fn load_recording(storage: &dyn Storage, room_id: &str) -> Result<Option<Recording>> {
+    let Some((snapshot, entries)) = storage.archive(room_id)? else {
+        return Ok(None);
+    };
+    let start = entries.first().map(|entry| entry.time);
+    let events = entries
+        .into_iter()
+        .map(|entry| RecordedEvent {
+            millis: start.map_or(0, |start| {
+                (entry.time - start).num_milliseconds().max(0) as u64
+            }),
+            event: entry.event,
+        })
+        .collect();
+    Ok(Some(Recording::new(&snapshot.puzzle, events)))
+}
+
+async fn get_denylist(ip_denylist: IpDenylist) -> Result<Response, Infallible> {
+    Ok(json(&*ip_denylist.read().await).into_response())
+}
+
+// newly denied addresses are kicked from every room
+async fn set_denylist(
+    new_denylist: Vec<String>,
+    ip_denylist: IpDenylist,
+    rooms: Rooms,
+) -> Result<Response, Infallible> {
+    info!("admin set ip denylist: {new_denylist:?}");
+
+    // saved under the lock so two updates can't land in the file out of order
+    let mut ip_denylist = ip_denylist.write().await;
+    let saved_denylist = new_denylist.clone();
+    let res = spawn_blocking(move || save_denylist(&saved_denylist))
+        .await
+        .unwrap();
+    if let Err(e) = res {
+        return Ok(with_status(
+            format!("couldn't save denylist: {e}"),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .into_response());
+    }
+    *ip_denylist = new_denylist.clone();
+    drop(ip_denylist);
+
+    for room in rooms.all().await {
+        for addr in &new_denylist {
+            room.kick_addr(addr).await;
+        }
+    }
+
+    Ok(StatusCode::OK.into_response())
+}
+
+// writes just the ip_denylist key so the rest of the config keeps its comments and layout
+fn save_denylist(denylist: &[String]) -> Result<()> {
+    let mut document: Document = read_to_string(CONFIG_FILE)?.parse()?;
+    document["ip_denylist"] = value(denylist.iter().map(String::as_str).collect::<Array>());
+    write(CONFIG_FILE, document.to_string())?;
+    Ok(())
+}
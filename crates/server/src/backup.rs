@@ -0,0 +1,118 @@
+use std::{
+    ffi::OsString,
+    fs::{read_to_string, remove_file, rename, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use log::{info, warn};
+
+use crate::storage::Snapshot;
+
+// the newest backup is at the configured path, older generations next to it with .1, .2
+// and so on appended
+pub struct PuzzleBackup {
+    file: PathBuf,
+    generations: usize,
+}
+
+impl PuzzleBackup {
+    pub fn new(file: PathBuf, generations: usize) -> Self {
+        Self {
+            file,
+            generations: generations.max(1),
+        }
+    }
+
+    fn generation(&self, generation: usize) -> PathBuf {
+        if generation == 0 {
+            return self.file.clone();
+        }
+        let mut file = OsString::from(self.file.as_os_str());
+        file.push(format!(".{generation}"));
+        file.into()
+    }
+
+    fn generations(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        (0..self.generations).map(|generation| self.generation(generation))
+    }
+
+    // the whole backup is on disk before it replaces anything, so a crash part way through
+    // leaves the older generations intact
+    pub fn write(&self, json: &str) -> Result<()> {
+        let temp_file = write_temp_file(&self.file, json)?;
+
+        for generation in (1..self.generations).rev() {
+            let older = self.generation(generation - 1);
+            if older.exists() {
+                rename(older, self.generation(generation))?;
+            }
+        }
+        rename(&temp_file, &self.file)?;
+        sync_dir(&self.file);
+        Ok(())
+    }
+
+    // the newest generation that deserializes, None if there isn't one
+    pub fn load(&self) -> Option<Snapshot> {
+        for file in self.generations() {
+            if !file.exists() {
+                continue;
+            }
+            match read_to_string(&file)
+                .map_err(anyhow::Error::from)
+                .and_then(|json| Snapshot::from_json(&json))
+            {
+                Ok(snapshot) => {
+                    info!("loaded puzzle backup {file:?}");
+                    return Some(snapshot);
+                }
+                Err(e) => warn!("skipping corrupt puzzle backup {file:?}: {e}"),
+            }
+        }
+        None
+    }
+
+    pub fn remove(&self) -> Result<()> {
+        for file in self.generations() {
+            if file.exists() {
+                remove_file(file)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// the whole file is on disk before it replaces the old one, so a crash part way through
+// leaves the old one intact
+pub fn write_atomically(path: &Path, contents: &str) -> Result<()> {
+    let temp_file = write_temp_file(path, contents)?;
+    rename(&temp_file, path)?;
+    sync_dir(path);
+    Ok(())
+}
+
+// <path>.tmp, synced
+fn write_temp_file(path: &Path, contents: &str) -> Result<PathBuf> {
+    let mut temp_file = OsString::from(path.as_os_str());
+    temp_file.push(".tmp");
+    let temp_file = PathBuf::from(temp_file);
+
+    let mut file = File::create(&temp_file)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    Ok(temp_file)
+}
+
+// so the renames survive a power cut too. not every platform can open a directory for
+// this, which only costs that guarantee
+fn sync_dir(file: &Path) {
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
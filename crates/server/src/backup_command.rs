@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Subcommand;
+
+use crate::storage::StoredBackup;
+
+#[derive(Subcommand)]
+pub enum BackupCommand {
+    /// Show the size and progress of a puzzle backup
+    Inspect {
+        /// Puzzle backup file
+        #[arg(required_unless_present = "room", conflicts_with = "room")]
+        backup: Option<PathBuf>,
+        #[command(flatten)]
+        stored: StoredBackup,
+    },
+}
+
+pub fn backup_command(command: BackupCommand) -> Result<()> {
+    match command {
+        BackupCommand::Inspect { backup, stored } => inspect(backup, stored),
+    }
+}
+
+fn inspect(backup: Option<PathBuf>, stored: StoredBackup) -> Result<()> {
+    let snapshot = stored.load(backup.as_deref())?;
+    let puzzle = &snapshot.puzzle;
+
+    let title = puzzle.options().title.as_deref().unwrap_or("untitled");
+    println!("title:    {title}");
+    println!(
+        "grid:     {}x{}, {} pieces of {}x{} px",
+        puzzle.num_cols(),
+        puzzle.num_rows(),
+        puzzle.piece_count(),
+        puzzle.piece_width(),
+        puzzle.piece_height()
+    );
+
+    let mut group_sizes = puzzle.group_sizes();
+    group_sizes.sort_unstable_by(|a, b| b.cmp(a));
+    let connected = group_sizes.iter().filter(|&&size| size > 1).count();
+    let largest = group_sizes.first().copied().unwrap_or(0);
+    println!(
+        "groups:   {} ({connected} with more than one piece, largest {largest})",
+        group_sizes.len()
+    );
+
+    let indices = puzzle.piece_indices();
+    let locked = indices
+        .iter()
+        .filter(|index| puzzle.piece_group_locked(index))
+        .count();
+    println!("locked:   {locked} pieces");
+    println!(
+        "complete: {:.1}%{}",
+        puzzle.percent_complete(),
+        if puzzle.is_complete() { " (done)" } else { "" }
+    );
+
+    let held: Vec<String> = indices
+        .iter()
+        .filter(|index| puzzle.piece_held(index))
+        .map(|index| format!("({}, {})", index.0, index.1))
+        .collect();
+    match held.is_empty() {
+        true => println!("held:     none"),
+        false => println!("held:     {}", held.join(" ")),
+    }
+    println!("journal:  through entry {}", snapshot.journal_seq);
+    Ok(())
+}
@@ -5,11 +5,8 @@ use futures::future::join;
 use futures_util::{SinkExt, StreamExt};
 use log::{error, info, warn};
 use tokio::{
-    sync::{
-        broadcast::{self, Sender},
-        mpsc::UnboundedSender,
-        RwLock,
-    },
+    select,
+    sync::{broadcast, RwLock},
     time::timeout,
 };
 use uuid::Uuid;
@@ -18,84 +15,93 @@ use warp::{
     Rejection, Reply,
 };
 
-use game::{AnyGameEvent, PlayerDisconnectedEvent, Puzzle};
+use game::{AnyGameEvent, PlayerDisconnectedEvent};
 
-use crate::server_game_event::ServerGameEvent;
+use crate::{
+    metrics::{event_type, EVENTS_RECEIVED, LAG_DISCONNECTS},
+    rooms::{Room, Rooms},
+    server_game_event::ServerGameEvent,
+};
+
+// shared with the admin api so it can be changed while the server runs
+pub type IpDenylist = Arc<RwLock<Vec<String>>>;
 
 pub async fn ws_handler(
+    room_id: String,
     remote: Option<SocketAddr>,
     ws: warp::ws::Ws,
-    puzzle: Arc<RwLock<Puzzle>>,
-    event_tx: UnboundedSender<ServerGameEvent>,
-    event_output_tx: Sender<ServerGameEvent>,
+    rooms: Rooms,
     client_timeout: Duration,
-    ip_denylist: Vec<String>,
+    ip_denylist: IpDenylist,
 ) -> Result<impl Reply, Rejection> {
-    Ok(ws.on_upgrade(move |warp_ws| {
-        client_handler(
-            remote,
-            warp_ws,
-            puzzle,
-            event_tx,
-            event_output_tx,
-            client_timeout,
-            ip_denylist,
-        )
-    }))
+    // before the room is opened, so denied addresses can't start rooms up
+    let Some(remote) = remote else {
+        error!("unexpected None type SocketAddr from client");
+        return Err(warp::reject::not_found());
+    };
+    let client_addr = remote.ip().to_string();
+    if ip_denylist.read().await.contains(&client_addr) {
+        return Err(warp::reject::not_found());
+    }
+
+    let room = match rooms.open(&room_id, None).await {
+        Ok(room) => room,
+        Err(e) => {
+            warn!("client couldn't join room {room_id}: {e}");
+            return Err(warp::reject::not_found());
+        }
+    };
+
+    Ok(ws.on_upgrade(move |warp_ws| client_handler(client_addr, warp_ws, room, client_timeout)))
 }
 
 pub async fn client_handler(
-    remote: Option<SocketAddr>,
+    client_addr: String,
     ws: WebSocket,
-    puzzle: Arc<RwLock<Puzzle>>,
-    event_tx: UnboundedSender<ServerGameEvent>,
-    event_output_tx: Sender<ServerGameEvent>,
+    room: Arc<Room>,
     client_timeout: Duration,
-    ip_denylist: Vec<String>,
 ) {
-    if remote.is_none() {
-        return;
-    }
-
-    let client_addr = match remote {
-        None => {
-            error!("unexpected None type SocketAddr from client");
-            return;
-        }
-        Some(addr) => {
-            let addr = addr.ip().to_string();
-            if ip_denylist.contains(&addr) {
-                return;
-            } else {
-                addr
-            }
-        }
-    };
-
     let client_id = Uuid::new_v4();
+    let mut kick_rx = room.add_client(client_id, client_addr.clone()).await;
 
-    info!("client {client_id} connected from: {client_addr}");
+    info!(
+        "client {client_id} connected to room {} from: {client_addr}",
+        room.id
+    );
 
     let (mut ws_tx, mut ws_rx) = ws.split();
 
     // subscribe to broadcast THEN serialize puzzle state while still holding the
     // read lock, so no events can sneak in between subscription and serialization
     let (mut event_rx, msg) = {
-        let puzzle = puzzle.read().await;
-        let event_rx = event_output_tx.subscribe();
+        let puzzle = room.puzzle.read().await;
+        let event_rx = room.event_output_tx.subscribe();
         let msg = Message::text(puzzle.serialize());
         (event_rx, msg)
     };
 
     if ws_tx.send(msg).await.is_err() {
+        room.remove_client(&client_id).await;
         info!("client {client_id} disconnected");
         return;
     }
 
+    let rx_room = room.clone();
+    let tx_room = room.clone();
+    let mut tx_kick_rx = kick_rx.clone();
+
     // receive client events and forward them to server event handler
     let client_rx_handler = async move {
         loop {
-            if let Ok(item) = timeout(client_timeout, ws_rx.next()).await {
+            let item = select! {
+                _ = kick_rx.changed() => {
+                    info!("client {client_id} kicked");
+                    break;
+                }
+                item = timeout(client_timeout, ws_rx.next()) => item,
+            };
+
+            if let Ok(item) = item {
                 let res = match item {
                     Some(res) => res,
                     None => {
@@ -111,9 +117,14 @@ pub async fn client_handler(
 
                 if msg.is_text() {
                     if let Ok(mut game_event) = AnyGameEvent::deserialize(msg.to_str().unwrap()) {
+                        EVENTS_RECEIVED
+                            .with_label_values(&[&rx_room.id, event_type(&game_event)])
+                            .inc();
+
                         match game_event {
                             AnyGameEvent::PieceConnection(_)
-                            | AnyGameEvent::PlayerDisconnected(_) => {
+                            | AnyGameEvent::PlayerDisconnected(_)
+                            | AnyGameEvent::PuzzleChanged(_) => {
                                 error!("received event from client {client_id} that only the server should generate: {game_event:#?}");
                                 break;
                             }
@@ -127,7 +138,7 @@ pub async fn client_handler(
                             game_event,
                         };
 
-                        if let Err(e) = event_tx.send(server_event) {
+                        if let Err(e) = rx_room.queue_event(server_event) {
                             error!(
                             "error sending event to server model in client {client_id} task: {e}"
                         );
@@ -149,7 +160,7 @@ pub async fn client_handler(
             }
         }
 
-        let res = event_tx.send(ServerGameEvent {
+        let res = rx_room.queue_event(ServerGameEvent {
             client_id,
             game_event: AnyGameEvent::PlayerDisconnected(PlayerDisconnectedEvent {
                 player_id: client_id,
@@ -165,15 +176,39 @@ pub async fn client_handler(
     // forward broadcasted events to client
     let client_tx_handler = async move {
         loop {
-            let event = match event_rx.recv().await {
+            let res = select! {
+                _ = tx_kick_rx.changed() => break,
+                res = event_rx.recv() => res,
+            };
+
+            let event = match res {
                 Ok(event) => event,
                 Err(broadcast::error::RecvError::Lagged(n)) => {
                     warn!("client {client_id} lagged by {n} events, forcing reconnect");
+                    LAG_DISCONNECTS.with_label_values(&[&tx_room.id]).inc();
                     break;
                 }
                 Err(broadcast::error::RecvError::Closed) => break,
             };
 
+            // start over from a new snapshot like a freshly connected client would
+            if let AnyGameEvent::PuzzleChanged(_) = event.game_event {
+                let msg = {
+                    let puzzle = tx_room.puzzle.read().await;
+                    event_rx = event_rx.resubscribe();
+                    Message::text(puzzle.serialize())
+                };
+                if ws_tx
+                    .send(Message::text(event.game_event.serialize()))
+                    .await
+                    .is_err()
+                    || ws_tx.send(msg).await.is_err()
+                {
+                    break;
+                }
+                continue;
+            }
+
             if event.client_id == client_id
                 && matches!(event.game_event, AnyGameEvent::PlayerDisconnected(_))
             {
@@ -194,7 +229,11 @@ pub async fn client_handler(
                 }
             }
         }
+
+        // closing makes the client hang up too, so the receiving side finishes
+        let _ = ws_tx.close().await;
     };
 
     join(client_rx_handler, client_tx_handler).await;
+    room.remove_client(&client_id).await;
 }
@@ -0,0 +1,269 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs::{
+        create_dir_all, read_to_string, remove_dir_all, remove_file, rename, write, File,
+        OpenOptions,
+    },
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use log::warn;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    backup::PuzzleBackup,
+    puzzle_loader::{ImageQueue, QueueEntry},
+    rooms::DEFAULT_ROOM,
+    storage::{CompletedPuzzle, JournalEntry, Snapshot, Storage},
+    Config,
+};
+
+struct RoomFiles {
+    queue: PathBuf,
+    backup: PathBuf,
+    events: PathBuf,
+    archive_start: PathBuf,
+    archive: PathBuf,
+    completed: PathBuf,
+}
+
+// the default room uses the queue and backup files from the config, with its event log and
+// completed puzzles next to the backup. other rooms keep everything in <rooms_dir>/<room id>/
+pub struct FileStorage {
+    config: Arc<Config>,
+    // event logs being appended to, by room. dropped before the file is replaced or removed
+    journals: Mutex<HashMap<String, BufWriter<File>>>,
+}
+
+impl FileStorage {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self {
+            config,
+            journals: Default::default(),
+        }
+    }
+
+    fn room_files(&self, room: &str) -> RoomFiles {
+        let (queue, backup) = if room == DEFAULT_ROOM {
+            (
+                self.config.queue_file.clone(),
+                self.config.puzzle_backup_file.clone(),
+            )
+        } else {
+            let dir = self.config.rooms_dir.join(room);
+            (dir.join("queue.toml"), dir.join("puzzle_backup.json"))
+        };
+        RoomFiles {
+            events: backup.with_file_name("puzzle_events.jsonl"),
+            archive_start: backup.with_file_name("puzzle_start.json"),
+            archive: backup.with_file_name("puzzle_events_archive.jsonl"),
+            completed: backup.with_file_name("completed.jsonl"),
+            queue,
+            backup,
+        }
+    }
+
+    fn backup(&self, room: &str) -> PuzzleBackup {
+        PuzzleBackup::new(
+            self.room_files(room).backup,
+            self.config.puzzle_backup_generations,
+        )
+    }
+
+    fn queue(&self, room: &str) -> ImageQueue {
+        ImageQueue::new(self.room_files(room).queue)
+    }
+}
+
+impl Storage for FileStorage {
+    fn room_exists(&self, room: &str) -> bool {
+        room == DEFAULT_ROOM || self.config.rooms_dir.join(room).is_dir()
+    }
+
+    fn create_room(&self, room: &str) -> Result<()> {
+        create_dir_all(self.config.rooms_dir.join(room))?;
+        Ok(())
+    }
+
+    fn remove_room(&self, room: &str) -> Result<()> {
+        self.journals.lock().unwrap().remove(room);
+        let dir = self.config.rooms_dir.join(room);
+        if dir.is_dir() {
+            remove_dir_all(dir)?;
+        }
+        Ok(())
+    }
+
+    fn save_snapshot(&self, room: &str, snapshot: &str) -> Result<()> {
+        self.backup(room).write(snapshot)
+    }
+
+    fn load_snapshot(&self, room: &str) -> Option<Snapshot> {
+        self.backup(room).load()
+    }
+
+    fn remove_snapshot(&self, room: &str) -> Result<()> {
+        self.backup(room).remove()
+    }
+
+    fn append_event(&self, room: &str, entry: &JournalEntry) -> Result<()> {
+        let mut journals = self.journals.lock().unwrap();
+        let journal = match journals.entry(room.to_string()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(self.room_files(room).events)?;
+                entry.insert(BufWriter::new(file))
+            }
+        };
+        serde_json::to_writer(&mut *journal, entry)?;
+        journal.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn sync_events(&self, room: &str) -> Result<()> {
+        if let Some(journal) = self.journals.lock().unwrap().get_mut(room) {
+            journal.flush()?;
+            journal.get_ref().sync_data()?;
+        }
+        Ok(())
+    }
+
+    fn events(&self, room: &str) -> Result<Vec<JournalEntry>> {
+        // so the read sees everything appended so far
+        if let Some(journal) = self.journals.lock().unwrap().get_mut(room) {
+            journal.flush()?;
+        }
+        read_json_lines(&self.room_files(room).events)
+    }
+
+    // the entries that are kept go to a new file that replaces the old one, so a crash part
+    // way through doesn't lose any
+    fn truncate_events(&self, room: &str, through_seq: u64) -> Result<()> {
+        // the open handle would keep writing to the replaced file
+        if let Some(mut journal) = self.journals.lock().unwrap().remove(room) {
+            journal.flush()?;
+        }
+        let files = self.room_files(room);
+        let (archived, kept): (Vec<JournalEntry>, Vec<JournalEntry>) =
+            read_json_lines::<JournalEntry>(&files.events)?
+                .into_iter()
+                .partition(|entry| entry.seq <= through_seq);
+
+        // a crash before the journal is replaced archives these again, which archive() skips
+        if !archived.is_empty() {
+            let mut archive = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&files.archive)?;
+            archive.write_all(json_lines(&archived)?.as_bytes())?;
+            archive.sync_data()?;
+        }
+
+        if kept.is_empty() {
+            if files.events.exists() {
+                remove_file(files.events)?;
+            }
+            return Ok(());
+        }
+
+        let temp_file = files.events.with_extension("jsonl.tmp");
+        write(&temp_file, json_lines(&kept)?)?;
+        rename(temp_file, files.events)?;
+        Ok(())
+    }
+
+    fn start_archive(&self, room: &str, snapshot: &str) -> Result<()> {
+        let files = self.room_files(room);
+        let temp_file = files.archive_start.with_extension("json.tmp");
+        write(&temp_file, snapshot)?;
+        rename(temp_file, files.archive_start)?;
+        if files.archive.exists() {
+            remove_file(files.archive)?;
+        }
+        Ok(())
+    }
+
+    fn archive(&self, room: &str) -> Result<Option<(Snapshot, Vec<JournalEntry>)>> {
+        let files = self.room_files(room);
+        if !files.archive_start.exists() {
+            return Ok(None);
+        }
+        let snapshot = Snapshot::from_json(&read_to_string(files.archive_start)?)?;
+
+        let mut entries: Vec<JournalEntry> = read_json_lines(&files.archive)?;
+        entries.extend(self.events(room)?);
+        let mut last_seq = snapshot.journal_seq;
+        entries.retain(|entry| {
+            let new = entry.seq > last_seq;
+            last_seq = last_seq.max(entry.seq);
+            new
+        });
+        Ok(Some((snapshot, entries)))
+    }
+
+    fn has_queue(&self, room: &str) -> bool {
+        self.queue(room).exists()
+    }
+
+    fn queue_entries(&self, room: &str) -> Result<Vec<QueueEntry>> {
+        self.queue(room).entries()
+    }
+
+    fn enqueue(&self, room: &str, entry: QueueEntry) -> Result<usize> {
+        self.queue(room).append(entry)
+    }
+
+    fn update_queue_entry(&self, room: &str, index: usize, entry: &QueueEntry) -> Result<()> {
+        self.queue(room).update(index, entry)
+    }
+
+    fn record_completion(&self, completed: &CompletedPuzzle) -> Result<()> {
+        append_json_line(&self.room_files(&completed.room).completed, completed)
+    }
+
+    fn completed_puzzles(&self, room: &str) -> Result<Vec<CompletedPuzzle>> {
+        read_json_lines(&self.room_files(room).completed)
+    }
+}
+
+fn append_json_line(path: &Path, value: &impl Serialize) -> Result<()> {
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+fn json_lines(values: &[impl Serialize]) -> Result<String> {
+    let mut contents = String::new();
+    for value in values {
+        contents.push_str(&serde_json::to_string(value)?);
+        contents.push('\n');
+    }
+    Ok(contents)
+}
+
+// a line cut short by a crash ends the file early rather than failing the whole read
+pub fn read_json_lines<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut values = Vec::new();
+    for line in read_to_string(path)?.lines() {
+        match serde_json::from_str(line) {
+            Ok(value) => values.push(value),
+            Err(e) => {
+                warn!("stopping at bad line in {path:?}: {e}");
+                break;
+            }
+        }
+    }
+    Ok(values)
+}
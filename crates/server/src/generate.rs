@@ -0,0 +1,75 @@
+use std::{
+    fs::{read, write},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use clap::Args;
+use log::info;
+
+use game::{contact_sheet, Puzzle};
+
+use crate::{puzzle_loader::QueueEntry, storage::Snapshot};
+
+#[derive(Args)]
+pub struct GenerateArgs {
+    /// Image to cut
+    image: PathBuf,
+    /// Snapshot json to write, which the server can start from
+    output: PathBuf,
+    /// Target piece count
+    #[arg(long)]
+    pieces: u32,
+    /// Puzzle option in the form key=value, the same ones queue entries take
+    #[arg(long = "option")]
+    options: Vec<String>,
+    /// Also write a png of every cut piece laid out in rows
+    #[arg(long)]
+    sheet: Option<PathBuf>,
+    /// Largest width or height of the contact sheet in pixels
+    #[arg(long, default_value_t = 4096)]
+    sheet_size: u32,
+}
+
+// cuts a puzzle the same way the queue would, to see what it comes out like before queueing it
+pub fn generate(args: GenerateArgs) -> Result<()> {
+    let mut entry = QueueEntry {
+        image: args.image.clone(),
+        pieces: Some(args.pieces),
+        ..Default::default()
+    };
+    for option in &args.options {
+        entry.apply_text_option(option)?;
+    }
+
+    let image = read(&args.image)?.into();
+    let original = Puzzle::image_from_bytes(&image)?;
+    let puzzle = entry.build_puzzle(image)?;
+
+    // whatever doesn't fit a whole number of pieces is cut off the right and bottom
+    let lost_x = original.width() - puzzle.image_width();
+    let lost_y = original.height() - puzzle.image_height();
+    let kept_area = puzzle.image_width() as f32 * puzzle.image_height() as f32;
+    let full_area = original.width() as f32 * original.height() as f32;
+    info!(
+        "grid {}x{}, {} pieces of {}x{} px",
+        puzzle.num_cols(),
+        puzzle.num_rows(),
+        puzzle.piece_count(),
+        puzzle.piece_width(),
+        puzzle.piece_height()
+    );
+    info!(
+        "cropped {lost_x} px from the right and {lost_y} px from the bottom, {:.1}% of the image",
+        100.0 * (1.0 - kept_area / full_area)
+    );
+
+    write(&args.output, Snapshot::to_json(&puzzle, 0))?;
+    info!("wrote {:?}", args.output);
+
+    if let Some(sheet) = &args.sheet {
+        contact_sheet(&puzzle, args.sheet_size).save(sheet)?;
+        info!("wrote contact sheet {sheet:?}");
+    }
+    Ok(())
+}
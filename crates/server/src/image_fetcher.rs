@@ -0,0 +1,294 @@
+use std::{
+    fs::{create_dir_all, write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use log::info;
+use reqwest::Client;
+use tokio::runtime::Handle;
+
+use crate::{upload::stored_image_path, Config};
+
+// downloads queue images given as http(s) urls instead of local paths
+#[derive(Clone)]
+pub struct ImageFetcher {
+    client: Client,
+    max_size: u64,
+    cache_dir: PathBuf,
+}
+
+impl ImageFetcher {
+    pub fn new(config: &Arc<Config>) -> Self {
+        Self {
+            client: Client::builder()
+                .timeout(config.download_timeout)
+                .build()
+                .unwrap(),
+            max_size: config.max_download_size,
+            cache_dir: config.download_cache_dir.clone(),
+        }
+    }
+
+    pub fn url(image: &Path) -> Option<&str> {
+        image
+            .to_str()
+            .filter(|image| image.starts_with("http://") || image.starts_with("https://"))
+    }
+
+    // the image and where it was cached. this waits on the download with
+    // Handle::current().block_on, so it has to run on one of the runtime's blocking threads,
+    // e.g. in spawn_blocking. on a worker thread it panics, and outside the runtime there's
+    // no handle to get
+    pub fn fetch(&self, url: &str) -> Result<(Bytes, PathBuf)> {
+        info!("Downloading {url}");
+        let image = Handle::current().block_on(self.download(url))?;
+
+        let extension = image::guess_format(&image)
+            .map_err(|_| anyhow!("{url} isn't a recognized image format"))?
+            .extensions_str()[0];
+        let cached_path = stored_image_path(&self.cache_dir, &image, extension);
+        if !cached_path.exists() {
+            create_dir_all(&self.cache_dir)?;
+            write(&cached_path, &image)?;
+        }
+        Ok((image, cached_path))
+    }
+
+    async fn download(&self, url: &str) -> Result<Bytes> {
+        let mut response = self.client.get(url).send().await?.error_for_status()?;
+        if response
+            .content_length()
+            .is_some_and(|length| length > self.max_size)
+        {
+            bail!("{url} is larger than {} bytes", self.max_size);
+        }
+
+        // the content length can't be trusted, so keep count while reading as well
+        let mut image = BytesMut::new();
+        while let Some(chunk) = response.chunk().await? {
+            if (image.len() + chunk.len()) as u64 > self.max_size {
+                bail!("{url} is larger than {} bytes", self.max_size);
+            }
+            image.put(chunk);
+        }
+        Ok(image.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::remove_dir_all, io::Cursor, time::Duration};
+
+    use image::{DynamicImage, ImageOutputFormat, RgbaImage};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        task::spawn_blocking,
+        time::sleep,
+    };
+
+    use super::*;
+    use crate::{
+        puzzle_loader::{PuzzleLoader, QueueEntry, QueueStatus},
+        sqlite_storage::SqliteStorage,
+        storage::Storage,
+    };
+
+    const MAX_SIZE: u64 = 10_000;
+
+    #[derive(Clone)]
+    enum Reply {
+        Body(Vec<u8>),
+        Status(&'static str),
+        // chunked, so there's no content length to check up front
+        Stream { chunks: usize, chunk_size: usize },
+        Hang,
+    }
+
+    // a stand-in http server answering each path with its reply, returns its address
+    async fn serve(routes: Vec<(&'static str, Reply)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(answer(stream, routes.clone()));
+            }
+        });
+        address
+    }
+
+    async fn answer(mut stream: TcpStream, routes: Vec<(&'static str, Reply)>) {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let read = stream.read(&mut buf).await.unwrap();
+            if read == 0 {
+                return;
+            }
+            request.extend_from_slice(&buf[..read]);
+        }
+        let request = String::from_utf8_lossy(&request);
+        let path = request.split_whitespace().nth(1).unwrap_or("/");
+        let reply = routes
+            .iter()
+            .find(|(route, _)| *route == path)
+            .map_or(Reply::Status("404 Not Found"), |(_, reply)| reply.clone());
+
+        let _ = match reply {
+            Reply::Body(body) => {
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    body.len()
+                );
+                stream.write_all(&[head.as_bytes(), &body].concat()).await
+            }
+            Reply::Status(status) => {
+                let head =
+                    format!("HTTP/1.1 {status}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n");
+                stream.write_all(head.as_bytes()).await
+            }
+            Reply::Stream { chunks, chunk_size } => {
+                let head = "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n";
+                if stream.write_all(head.as_bytes()).await.is_err() {
+                    return;
+                }
+                for _ in 0..chunks {
+                    let chunk = format!("{chunk_size:x}\r\n{}\r\n", "x".repeat(chunk_size));
+                    if stream.write_all(chunk.as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+                stream.write_all(b"0\r\n\r\n").await
+            }
+            Reply::Hang => {
+                sleep(Duration::from_secs(30)).await;
+                Ok(())
+            }
+        };
+    }
+
+    fn png() -> Vec<u8> {
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(RgbaImage::new(32, 32))
+            .write_to(&mut png, ImageOutputFormat::Png)
+            .unwrap();
+        png.into_inner()
+    }
+
+    fn fetcher(name: &str) -> ImageFetcher {
+        ImageFetcher {
+            client: Client::builder()
+                .timeout(Duration::from_millis(500))
+                .build()
+                .unwrap(),
+            max_size: MAX_SIZE,
+            cache_dir: std::env::temp_dir()
+                .join(format!("image_fetcher_{name}_{}", std::process::id())),
+        }
+    }
+
+    async fn fetch(fetcher: &ImageFetcher, url: String) -> Result<(Bytes, PathBuf)> {
+        let fetcher = fetcher.clone();
+        spawn_blocking(move || fetcher.fetch(&url)).await.unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn fetches_and_caches_image() {
+        let png = png();
+        let address = serve(vec![("/image.png", Reply::Body(png.clone()))]).await;
+        let fetcher = fetcher("caches");
+
+        let (image, cached_path) = fetch(&fetcher, format!("{address}/image.png"))
+            .await
+            .unwrap();
+        assert_eq!(image.as_ref(), png.as_slice());
+        assert_eq!(std::fs::read(&cached_path).unwrap(), png);
+
+        remove_dir_all(fetcher.cache_dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_oversized_content_length() {
+        let body = Reply::Body(vec![0; MAX_SIZE as usize + 1]);
+        let address = serve(vec![("/big", body)]).await;
+
+        let error = fetch(&fetcher("length"), format!("{address}/big"))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("larger than"), "{error}");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_oversized_stream_without_content_length() {
+        let stream = Reply::Stream {
+            chunks: 1000,
+            chunk_size: 1000,
+        };
+        let address = serve(vec![("/stream", stream)]).await;
+
+        let error = fetch(&fetcher("stream"), format!("{address}/stream"))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("larger than"), "{error}");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn times_out() {
+        let address = serve(vec![("/slow", Reply::Hang)]).await;
+
+        let error = fetch(&fetcher("timeout"), format!("{address}/slow"))
+            .await
+            .unwrap_err();
+        let error = error.downcast::<reqwest::Error>().unwrap();
+        assert!(error.is_timeout(), "{error}");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_error_status() {
+        let address = serve(vec![("/gone", Reply::Status("410 Gone"))]).await;
+
+        let error = fetch(&fetcher("status"), format!("{address}/gone"))
+            .await
+            .unwrap_err();
+        let error = error.downcast::<reqwest::Error>().unwrap();
+        assert_eq!(error.status().map(|status| status.as_u16()), Some(410));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn falls_back_to_next_entry() {
+        let address = serve(vec![("/image.png", Reply::Body(png()))]).await;
+        let fetcher = fetcher("fallback");
+        let db = fetcher.cache_dir.with_extension("db");
+        let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::open(&db).unwrap());
+        for image in ["missing.png", "image.png"] {
+            let entry = QueueEntry {
+                image: format!("{address}/{image}").into(),
+                grid: Some((2, 2)),
+                ..Default::default()
+            };
+            storage.enqueue("room", entry).unwrap();
+        }
+
+        let loader_storage = storage.clone();
+        let loader_fetcher = fetcher.clone();
+        let puzzle = spawn_blocking(move || {
+            PuzzleLoader::new(loader_storage, String::from("room"), loader_fetcher).next()
+        })
+        .await
+        .unwrap();
+        assert_eq!(puzzle.unwrap().piece_count(), 4);
+
+        let entries = storage.queue_entries("room").unwrap();
+        assert_eq!(entries[0].status, QueueStatus::Failed);
+        assert!(entries[0].error.as_ref().unwrap().contains("404"));
+        assert_eq!(entries[1].status, QueueStatus::Current);
+        assert!(entries[1].cached_image.as_ref().unwrap().exists());
+
+        remove_dir_all(fetcher.cache_dir).unwrap();
+        std::fs::remove_file(db).unwrap();
+    }
+}
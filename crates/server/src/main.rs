@@ -1,34 +1,69 @@
-use std::{
-    fs::{read_to_string, write},
-    path::PathBuf,
-    pin::Pin,
-    sync::Arc,
-    time::Duration,
-};
+use std::{fs::read_to_string, path::PathBuf, pin::Pin, sync::Arc, time::Duration};
 
-use clap::Parser;
+use anyhow::Result;
+use clap::{Parser, Subcommand};
 use futures_util::{future::join, Future, FutureExt};
-use log::{info, warn};
+use log::{error, info, warn};
 use serde::Deserialize;
 use serde_with::{serde_as, DurationSeconds};
-use tokio::{
-    select,
-    sync::{broadcast, mpsc::unbounded_channel, RwLock},
-    time::sleep,
-};
+use tokio::{sync::RwLock, task::spawn_blocking};
 use warp::{hyper::Uri, Filter};
 
 use game::Puzzle;
 
 automod::dir!("src/");
 
-use crate::{clients::ws_handler, puzzle_loader::PuzzleLoader, server_game_event::ServerGameEvent};
+use crate::{
+    admin::admin_routes,
+    backup_command::{backup_command, BackupCommand},
+    clients::ws_handler,
+    generate::{generate, GenerateArgs},
+    metrics::metrics_handler,
+    queue_command::{queue_command, QueueCommand},
+    render::{render, RenderArgs},
+    rooms::{Rooms, DEFAULT_ROOM},
+    storage::{open_storage, StorageBackend},
+    timelapse::{timelapse, TimelapseArgs},
+    watch_folder::watch_folder,
+};
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    // without a command the server starts, same as with serve
+    #[command(flatten)]
+    serve: ServeArgs,
+}
+
+#[derive(clap::Args)]
+struct ServeArgs {
+    /// Puzzle to start the default room with instead of its backup or queue
     puzzle_json: Option<PathBuf>,
 }
 
+// everything but serve runs and exits
+#[derive(Subcommand)]
+enum Command {
+    /// Start the server
+    Serve(ServeArgs),
+    /// Look at or change a room's image queue while the server is stopped
+    #[command(subcommand)]
+    Queue(QueueCommand),
+    /// Look at puzzle backups
+    #[command(subcommand)]
+    Backup(BackupCommand),
+    /// Render frames of a puzzle being put together from a backup and its event journal
+    Timelapse(TimelapseArgs),
+    /// Draw a puzzle backup as a png, with every piece where it was left
+    Render(RenderArgs),
+    /// Cut an image into a puzzle and write it out as json, without starting the server
+    Generate(GenerateArgs),
+}
+
+pub const CONFIG_FILE: &str = "server_config.toml";
+
 #[serde_as]
 #[derive(Deserialize, Debug)]
 struct Config {
@@ -45,6 +80,10 @@ struct Config {
     #[serde_as(as = "DurationSeconds")]
     puzzle_backup_interval: Duration,
     puzzle_backup_file: PathBuf,
+    puzzle_backup_generations: usize,
+
+    storage: StorageBackend,
+    sqlite_file: PathBuf,
 
     #[serde_as(as = "DurationSeconds")]
     completion_check_interval: Duration,
@@ -52,11 +91,28 @@ struct Config {
     complete_wait_time: Duration,
 
     queue_file: PathBuf,
+    rooms_dir: PathBuf,
 
     tls_cert: PathBuf,
     tls_key: PathBuf,
 
     acme_webroot: PathBuf,
+
+    admin_token: Option<String>,
+
+    upload_dir: PathBuf,
+    max_upload_size: u64,
+    max_upload_dimension: u32,
+
+    download_cache_dir: PathBuf,
+    max_download_size: u64,
+    #[serde_as(as = "DurationSeconds")]
+    download_timeout: Duration,
+
+    watch_dir: Option<PathBuf>,
+    watch_default_pieces: u32,
+    #[serde_as(as = "DurationSeconds")]
+    watch_interval: Duration,
 }
 
 #[tokio::main]
@@ -68,25 +124,53 @@ async fn main() {
     info!("build: {}", option_env!("GIT_HASH").unwrap_or("unknown"));
 
     let args = Args::parse();
+    let res = match args.command.unwrap_or(Command::Serve(args.serve)) {
+        Command::Serve(args) => serve(args).await,
+        // off the runtime's threads, the image fetcher blocks on it for urls in the queue
+        Command::Queue(command) => {
+            spawn_blocking(move || load_config().and_then(|config| queue_command(command, config)))
+                .await
+                .unwrap()
+        }
+        Command::Backup(command) => backup_command(command),
+        Command::Timelapse(args) => timelapse(args),
+        Command::Render(args) => render(args),
+        Command::Generate(args) => generate(args),
+    };
+    if let Err(e) = res {
+        error!("{e}");
+        std::process::exit(1);
+    }
+}
 
-    let config_string = read_to_string("server_config.toml").unwrap();
-    let config: Config = toml::from_str(&config_string).unwrap();
-    info!("loaded config: {config:#?}");
+fn load_config() -> Result<Arc<Config>> {
+    let config_string = read_to_string(CONFIG_FILE)?;
+    Ok(Arc::new(toml::from_str(&config_string)?))
+}
 
-    let mut puzzle_loader = PuzzleLoader::new(config.queue_file);
+async fn serve(args: ServeArgs) -> Result<()> {
+    let config = load_config()?;
+    info!("loaded config: {config:#?}");
 
-    let puzzle = if let Some(puzzle_json) = args.puzzle_json {
-        info!("loading puzzle from {:?}", puzzle_json);
-        Puzzle::deserialize(&read_to_string(&puzzle_json).unwrap()).unwrap()
-    } else {
-        puzzle_loader.next().unwrap()
+    let puzzle = match args.puzzle_json {
+        Some(puzzle_json) => {
+            info!("loading puzzle from {:?}", puzzle_json);
+            Some(Puzzle::deserialize(&read_to_string(&puzzle_json)?)?)
+        }
+        None => None,
     };
 
-    let puzzle = Arc::new(RwLock::new(puzzle));
-    let need_backup = Arc::new(RwLock::new(true));
+    // other rooms start up when their first client connects
+    let storage = open_storage(config.clone())?;
+    let rooms = Rooms::new(config.clone(), storage);
+    if let Err(e) = rooms.open(DEFAULT_ROOM, puzzle).await {
+        warn!("couldn't open default room: {e}");
+    }
 
-    let (event_input_tx, mut event_input_rx) = unbounded_channel::<ServerGameEvent>();
-    let (event_output_tx, _) = broadcast::channel::<ServerGameEvent>(config.broadcast_channel_size);
+    // images dropped into the watch folder go to the default room's queue
+    if let Some(watch_dir) = config.watch_dir.clone() {
+        tokio::spawn(watch_folder(watch_dir, rooms.storage(), config.clone()));
+    }
 
     // ACME challenge handler for certbot --webroot renewal
     let acme_challenge = warp::path(".well-known")
@@ -98,22 +182,42 @@ async fn main() {
     // route that serves up the client application
     let http_route = warp::fs::dir("dist");
 
-    // client route that gives them a puzzle ref and channel handles
-    let puzzle_clone = puzzle.clone();
-    let event_output_tx_clone = event_output_tx.clone();
+    // the client application again for /room/<room id>, it picks its room from the path
+    let room_page_route = warp::path("room")
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::fs::file("dist/index.html"))
+        .map(|_: String, file| file);
+
+    // client route that gives them a handle to the room in /client/<room id>, or the
+    // default room for plain /client
+    let room_id = warp::path::param::<String>()
+        .and(warp::path::end())
+        .or(warp::path::end().map(|| String::from(DEFAULT_ROOM)))
+        .unify();
+    let client_timeout = config.client_timeout;
+    let ip_denylist = Arc::new(RwLock::new(config.ip_denylist.clone()));
+    let ip_denylist_clone = ip_denylist.clone();
+    let rooms_clone = rooms.clone();
     let client_route = warp::path("client")
+        .and(room_id)
         .and(warp::filters::addr::remote())
         .and(warp::ws())
-        .and(warp::any().map(move || puzzle_clone.clone()))
-        .and(warp::any().map(move || event_input_tx.clone()))
-        .and(warp::any().map(move || event_output_tx_clone.clone()))
-        .and(warp::any().map(move || config.client_timeout))
-        .and(warp::any().map(move || config.ip_denylist.clone()))
+        .and(warp::any().map(move || rooms_clone.clone()))
+        .and(warp::any().map(move || client_timeout))
+        .and(warp::any().map(move || ip_denylist_clone.clone()))
         .and_then(ws_handler);
 
+    // prometheus scrape target
+    let metrics_route = warp::path!("metrics").and_then(metrics_handler);
+
+    let admin_route = admin_routes(rooms, ip_denylist, config.clone());
+
     // ACME challenge is on the main routes too so it works even if port 80
     // traffic is forwarded to this port via iptables
-    let routes = acme_challenge.or(warp::get().and(http_route).or(client_route));
+    let routes = acme_challenge.or(admin_route).or(warp::get()
+        .and(metrics_route.or(http_route).or(room_page_route))
+        .or(client_route));
     let serve = warp::serve(routes);
 
     // don't use tls if dev
@@ -123,8 +227,8 @@ async fn main() {
     } else {
         let tls = serve
             .tls()
-            .cert_path(config.tls_cert)
-            .key_path(config.tls_key)
+            .cert_path(&config.tls_cert)
+            .key_path(&config.tls_key)
             .run(([0, 0, 0, 0], config.port));
 
         // redirect HTTP to HTTPS, preserving the request path
@@ -138,54 +242,6 @@ async fn main() {
         Box::pin(join(tls, redirect).map(|_| ()))
     };
 
-    // apply events to the puzzle and dispatch the generated events to clients
-    let puzzle_clone = puzzle.clone();
-    let need_backup_clone = need_backup.clone();
-    let event_handler = async move {
-        while let Some(server_event) = event_input_rx.recv().await {
-            let res_events = puzzle_clone
-                .write()
-                .await
-                .apply_event(server_event.game_event);
-            for res_event in res_events {
-                let _ = event_output_tx.send(ServerGameEvent {
-                    client_id: server_event.client_id,
-                    game_event: res_event,
-                });
-            }
-            *need_backup_clone.write().await = true;
-        }
-    };
-
-    let puzzle_clone = puzzle.clone();
-    let puzzle_backup = async move {
-        loop {
-            sleep(config.puzzle_backup_interval).await;
-
-            if config.backup_puzzle && *need_backup.read().await {
-                *need_backup.write().await = false;
-                let json = puzzle_clone.read().await.serialize();
-                write(&config.puzzle_backup_file, json).unwrap();
-            }
-        }
-    };
-
-    let completion_handler = async move {
-        while !puzzle.read().await.is_complete() {
-            sleep(config.completion_check_interval).await;
-        }
-
-        info!("puzzle complete!");
-        info!("shutting down server in {:?}...", config.complete_wait_time);
-
-        puzzle_loader.pop_current();
-        sleep(config.complete_wait_time).await;
-    };
-
-    select! {
-        _ = serve => panic!(),
-        _ = event_handler => panic!(),
-        _ = puzzle_backup => panic!(),
-        _ = completion_handler => (),
-    };
+    serve.await;
+    Ok(())
 }
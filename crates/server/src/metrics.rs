@@ -0,0 +1,108 @@
+use std::{convert::Infallible, sync::LazyLock};
+
+use log::error;
+use prometheus::{
+    register_gauge_vec, register_histogram_vec, register_int_counter_vec, register_int_gauge_vec,
+    GaugeVec, HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use warp::{http::StatusCode, reply::Response, Reply};
+
+use game::AnyGameEvent;
+
+pub static CONNECTED_CLIENTS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "connected_clients",
+        "Clients connected to a room",
+        &["room"]
+    )
+    .unwrap()
+});
+
+pub static EVENTS_RECEIVED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "events_received_total",
+        "Game events received from clients",
+        &["room", "type"]
+    )
+    .unwrap()
+});
+
+pub static LAG_DISCONNECTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lag_disconnects_total",
+        "Clients disconnected for falling behind the event broadcast",
+        &["room"]
+    )
+    .unwrap()
+});
+
+pub static EVENT_QUEUE_DEPTH: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "event_queue_depth",
+        "Client events waiting for a room's event handler",
+        &["room"]
+    )
+    .unwrap()
+});
+
+pub static APPLY_EVENT_SECONDS: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "apply_event_seconds",
+        "Time taken to apply a game event to the puzzle",
+        &["type"],
+        vec![0.00001, 0.00005, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1]
+    )
+    .unwrap()
+});
+
+pub static BACKUP_SECONDS: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "backup_seconds",
+        "Time taken to write a puzzle backup",
+        &["room"]
+    )
+    .unwrap()
+});
+
+pub static PUZZLE_PERCENT_COMPLETE: LazyLock<GaugeVec> = LazyLock::new(|| {
+    register_gauge_vec!(
+        "puzzle_percent_complete",
+        "Progress of the puzzle in a room",
+        &["room"]
+    )
+    .unwrap()
+});
+
+// drop a closed room's series so they don't hang around at their last value
+pub fn remove_room(room_id: &str) {
+    let _ = CONNECTED_CLIENTS.remove_label_values(&[room_id]);
+    let _ = EVENT_QUEUE_DEPTH.remove_label_values(&[room_id]);
+    let _ = PUZZLE_PERCENT_COMPLETE.remove_label_values(&[room_id]);
+}
+
+pub fn event_type(event: &AnyGameEvent) -> &'static str {
+    use AnyGameEvent::*;
+    match event {
+        PieceMoved(_) => "piece_moved",
+        PiecePickedUp(_) => "piece_picked_up",
+        PiecePutDown(_) => "piece_put_down",
+        PieceConnectionCheck(_) => "piece_connection_check",
+        PieceConnection(_) => "piece_connection",
+        PieceFlipped(_) => "piece_flipped",
+        PlayerCursorMoved(_) => "player_cursor_moved",
+        PlayerDisconnected(_) => "player_disconnected",
+        PuzzleChanged(_) => "puzzle_changed",
+    }
+}
+
+pub async fn metrics_handler() -> Result<Response, Infallible> {
+    Ok(
+        match TextEncoder::new().encode_to_string(&prometheus::gather()) {
+            Ok(metrics) => metrics.into_response(),
+            Err(e) => {
+                error!("couldn't encode metrics: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+    )
+}
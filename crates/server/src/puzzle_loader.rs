@@ -1,38 +1,116 @@
 use std::{
-    fs::{write, File},
-    io::{BufRead, BufReader, Read},
-    path::PathBuf,
+    fs::{read_to_string, File},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use toml::value::Datetime;
+use toml_edit::{value, ArrayOfTables, Document, Item, Table};
 
-use game::Puzzle;
+use game::{
+    Color, CutStyle, LockRule, Outline, PieceBack, Puzzle, PuzzleOptions, StartFace, Whimsy,
+};
+
+use crate::{backup::write_atomically, image_fetcher::ImageFetcher, storage::Storage};
 
 pub struct PuzzleLoader {
-    queue: ImageQueue,
-    current_entry: Option<ImageQueueEntry>,
+    storage: Arc<dyn Storage>,
+    room: String,
+    current_entry: Option<(usize, QueueEntry)>,
+    image_fetcher: ImageFetcher,
 }
 
 impl PuzzleLoader {
-    pub fn new(queue_file: PathBuf) -> Self {
+    pub fn new(storage: Arc<dyn Storage>, room: String, image_fetcher: ImageFetcher) -> Self {
         Self {
-            queue: ImageQueue::new(queue_file),
+            storage,
+            room,
             current_entry: None,
+            image_fetcher,
+        }
+    }
+
+    pub fn has_queue(&self) -> bool {
+        self.storage.has_queue(&self.room)
+    }
+
+    fn entries(&self) -> Vec<QueueEntry> {
+        self.storage.queue_entries(&self.room).unwrap_or_else(|e| {
+            warn!("Error reading queue for room {}: {e}", self.room);
+            Vec::new()
+        })
+    }
+
+    fn stored_current_entry(&self) -> Option<(usize, QueueEntry)> {
+        self.entries()
+            .into_iter()
+            .enumerate()
+            .find(|(_, entry)| entry.status == QueueStatus::Current)
+    }
+
+    // take over the entry a restored backup was made from, so it's the one marked done when
+    // the restored puzzle is finished
+    pub fn resume(&mut self) {
+        self.current_entry = self.stored_current_entry();
+    }
+
+    pub fn current_entry(&self) -> Option<&QueueEntry> {
+        self.current_entry.as_ref().map(|(_, entry)| entry)
+    }
+
+    // whether there are queued puzzles, even ones that aren't due to start yet
+    pub fn has_pending(&self) -> bool {
+        self.entries()
+            .iter()
+            .any(|entry| entry.status == QueueStatus::Queued)
+    }
+
+    // an entry still marked current that nobody is holding means the server went down before
+    // its puzzle was backed up, so that one goes first
+    fn next_entry(&self) -> Option<(usize, QueueEntry)> {
+        self.stored_current_entry().or_else(|| {
+            self.entries()
+                .into_iter()
+                .enumerate()
+                .find(|(_, entry)| entry.status == QueueStatus::Queued && entry.is_due())
+        })
+    }
+
+    fn update_entry(&self, index: usize, entry: &QueueEntry) {
+        if let Err(e) = self.storage.update_queue_entry(&self.room, index, entry) {
+            warn!("Error updating queue for room {}: {e}", self.room);
         }
     }
 
-    fn load_puzzle(entry: &ImageQueueEntry) -> Result<Puzzle> {
+    // index is the entry's place in the queue, for saving where a url was downloaded to
+    pub fn load_puzzle(&self, index: usize, entry: &mut QueueEntry) -> Result<Puzzle> {
         info!("Loading {entry:?}");
-        let mut file = File::open(&entry.image_path)?;
-        let mut buf = Vec::new();
-        file.read_to_end(&mut buf)?;
-        Puzzle::new(buf.into(), entry.target_piece_count, true)
+        let image = match ImageFetcher::url(&entry.image) {
+            // downloaded images are remembered in the entry so restarts don't fetch them again
+            Some(url) => match entry.cached_image.as_ref().filter(|path| path.exists()) {
+                Some(cached_image) => read_image(cached_image)?,
+                None => {
+                    let (image, cached_image) = self.image_fetcher.fetch(url)?;
+                    entry.cached_image = Some(cached_image);
+                    self.update_entry(index, entry);
+                    image
+                }
+            },
+            None => read_image(&entry.image)?,
+        };
+        entry.build_puzzle(image)
     }
 
     pub fn pop_current(&mut self) {
-        if let Some(entry) = self.current_entry.take() {
-            self.queue.comment_out_entry(&entry)
+        if let Some((index, mut entry)) = self.current_entry.take() {
+            entry.status = QueueStatus::Done;
+            self.update_entry(index, &entry);
         }
     }
 }
@@ -43,15 +121,19 @@ impl Iterator for PuzzleLoader {
     fn next(&mut self) -> Option<Self::Item> {
         self.pop_current();
 
-        while let Some(entry) = self.queue.top_entry() {
-            match Self::load_puzzle(&entry) {
+        while let Some((index, mut entry)) = self.next_entry() {
+            match self.load_puzzle(index, &mut entry) {
                 Ok(puzzle) => {
-                    self.current_entry = Some(entry);
+                    entry.status = QueueStatus::Current;
+                    self.update_entry(index, &entry);
+                    self.current_entry = Some((index, entry));
                     return Some(puzzle);
                 }
                 Err(e) => {
                     warn!("Error loading entry {entry:?}: {e}");
-                    self.queue.comment_out_entry(&entry)
+                    entry.status = QueueStatus::Failed;
+                    entry.error = Some(e.to_string());
+                    self.update_entry(index, &entry);
                 }
             }
         }
@@ -60,92 +142,410 @@ impl Iterator for PuzzleLoader {
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
-struct ImageQueueEntry {
-    pub target_piece_count: u32,
-    pub image_path: PathBuf,
+fn read_image(path: &Path) -> Result<Bytes> {
+    let mut file = File::open(path)?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    Ok(buf.into())
 }
 
-impl ImageQueueEntry {
-    pub fn from_line(line: &str, quiet: bool) -> Option<Self> {
-        let mut split = line.split_whitespace();
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueStatus {
+    #[default]
+    Queued,
+    Current,
+    Done,
+    Failed,
+}
+
+impl QueueStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Current => "current",
+            Self::Done => "done",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct QueueFile {
+    #[serde(default, rename = "puzzle")]
+    puzzles: Vec<QueueEntry>,
+}
+
+// one [[puzzle]] table in the queue file, e.g.
+//
+// [[puzzle]]
+// image = "images/boats.jpg" # or an http(s) url
+// pieces = 500
+// title = "Boats"
+// attribution = "Photo by someone"
+// cut = "hexagonal"
+// lock = "edges"
+// start_time = 2024-06-01T18:00:00Z
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct QueueEntry {
+    pub image: PathBuf,
+    // where an image given as a url was downloaded to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached_image: Option<PathBuf>,
+
+    // either a target piece count or exact [columns, rows]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pieces: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grid: Option<(u32, u32)>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<String>,
 
-        let first = split.next()?;
+    pub cut: CutStyle,
+    pub lock: LockRule,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection_tolerance: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lock_tolerance: Option<f32>,
+    pub edgeless: bool,
+    // "alpha" or a path to a silhouette image
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outline: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whimsy_dir: Option<PathBuf>,
+    // a hex color like "#336699" or a path to an image
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub back: Option<String>,
+    pub start: StartFace,
 
-        if first.starts_with('#') {
-            return None;
+    // not loaded before this time, datetimes without an offset are in the server's time zone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<Datetime>,
+
+    pub status: QueueStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl QueueEntry {
+    // the image is passed in rather than read from the entry's path so it can be checked
+    // before it's saved anywhere
+    pub fn build_puzzle(&self, image: Bytes) -> Result<Puzzle> {
+        let options = self.puzzle_options()?;
+        Puzzle::new(image, self.target_piece_count()?, true, options)
+    }
+
+    fn target_piece_count(&self) -> Result<u32> {
+        match (self.pieces, self.grid) {
+            (Some(pieces), _) => Ok(pieces),
+            (None, Some((num_cols, num_rows))) => num_cols
+                .checked_mul(num_rows)
+                .ok_or_else(|| anyhow!("grid {num_cols}x{num_rows} is too big")),
+            (None, None) => bail!("queue entry needs a piece count or a grid"),
         }
+    }
 
-        let target_piece_count = match first.parse() {
-            Ok(target) => target,
-            Err(e) => {
-                if !quiet {
-                    warn!("Error parsing target piece count: {e}");
-                }
-                return None;
-            }
+    fn puzzle_options(&self) -> Result<PuzzleOptions> {
+        let mut options = PuzzleOptions {
+            lock_rule: self.lock,
+            edgeless: self.edgeless,
+            cut_style: self.cut,
+            start_face: self.start,
+            grid: self.grid,
+            title: self.title.clone(),
+            attribution: self.attribution.clone(),
+            ..Default::default()
         };
+        if let Some(tolerance) = self.connection_tolerance {
+            options.connection_tolerance = check_tolerance(tolerance)?;
+        }
+        if let Some(tolerance) = self.lock_tolerance {
+            options.lock_tolerance = check_tolerance(tolerance)?;
+        }
+        if let Some(outline) = &self.outline {
+            options.outline = parse_outline(outline)?;
+        }
+        if let Some(whimsy_dir) = &self.whimsy_dir {
+            options.whimsies = load_whimsies(whimsy_dir)?;
+        }
+        if let Some(back) = &self.back {
+            options.back = parse_back(back)?;
+        }
+        Ok(options)
+    }
 
-        let image_path = match split.next() {
-            Some(string) => string,
-            None => {
-                warn!("Image queue entry missing image path");
-                return None;
-            }
+    fn is_due(&self) -> bool {
+        match &self.start_time {
+            None => true,
+            Some(start_time) => match parse_start_time(start_time) {
+                Ok(start_time) => start_time <= Utc::now(),
+                Err(e) => {
+                    warn!("Ignoring start time of {:?}: {e}", self.image);
+                    true
+                }
+            },
+        }
+    }
+
+    // old queue.txt option in the form key=value
+    pub fn apply_text_option(&mut self, option: &str) -> Result<()> {
+        let (key, value) = option
+            .split_once('=')
+            .ok_or_else(|| anyhow!("malformed puzzle option: {option}"))?;
+        match key {
+            "lock" => self.lock = value.parse()?,
+            "connection_tolerance" => self.connection_tolerance = Some(value.parse()?),
+            "lock_tolerance" => self.lock_tolerance = Some(value.parse()?),
+            "edgeless" => self.edgeless = value.parse()?,
+            "outline" => self.outline = Some(value.to_string()),
+            "cut" => self.cut = value.parse()?,
+            "whimsy_dir" => self.whimsy_dir = Some(value.into()),
+            "back" => self.back = Some(value.to_string()),
+            "start" => self.start = value.parse()?,
+            _ => bail!("unknown puzzle option: {key}"),
+        }
+        Ok(())
+    }
+
+    // <target piece count> <image path> [option=value ...]
+    // e.g. 500 images/boats.jpg lock=edges connection_tolerance=0.3 cut=hexagonal
+    fn from_text_line(line: &str) -> Result<Self> {
+        let mut split = line.split_whitespace();
+
+        let pieces = split
+            .next()
+            .ok_or_else(|| anyhow!("empty line"))?
+            .parse()
+            .map_err(|e| anyhow!("error parsing target piece count: {e}"))?;
+        let image = split
+            .next()
+            .ok_or_else(|| anyhow!("image queue entry missing image path"))?;
+
+        let mut entry = Self {
+            image: image.into(),
+            pieces: Some(pieces),
+            ..Default::default()
         };
+        for option in split {
+            entry.apply_text_option(option)?;
+        }
+        Ok(entry)
+    }
+}
 
-        Some(Self {
-            target_piece_count,
-            image_path: image_path.into(),
-        })
+fn parse_start_time(start_time: &Datetime) -> Result<DateTime<Utc>> {
+    let string = start_time.to_string();
+    if let Ok(start_time) = DateTime::parse_from_rfc3339(&string) {
+        return Ok(start_time.into());
+    }
+
+    let local = if let Ok(datetime) = NaiveDateTime::parse_from_str(&string, "%Y-%m-%dT%H:%M:%S%.f")
+    {
+        datetime
+    } else {
+        NaiveDate::parse_from_str(&string, "%Y-%m-%d")?
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    };
+    Local
+        .from_local_datetime(&local)
+        .earliest()
+        .map(Into::into)
+        .ok_or_else(|| anyhow!("start time {string} doesn't exist in the local time zone"))
+}
+
+fn check_tolerance(tolerance: f32) -> Result<f32> {
+    if !tolerance.is_finite() || tolerance < 0.0 {
+        bail!("invalid snap tolerance: {tolerance}");
     }
+    Ok(tolerance)
 }
 
-struct ImageQueue {
+// either "alpha" to use the image's own transparency or a path to a silhouette image
+fn parse_outline(value: &str) -> Result<Outline> {
+    if value == "alpha" {
+        return Ok(Outline::Alpha);
+    }
+    let silhouette = std::fs::read(value)
+        .map_err(|e| anyhow!("couldn't read outline silhouette {value}: {e}"))?;
+    Ok(Outline::Silhouette(silhouette.into()))
+}
+
+// either a hex color like #336699 or a path to an image for the back of the puzzle
+fn parse_back(value: &str) -> Result<PieceBack> {
+    if value.starts_with('#') {
+        let color = Color::hex(value).map_err(|e| anyhow!("invalid back color {value}: {e}"))?;
+        return Ok(PieceBack::Color(color));
+    }
+    let image =
+        std::fs::read(value).map_err(|e| anyhow!("couldn't read back image {value}: {e}"))?;
+    Ok(PieceBack::Image(image.into()))
+}
+
+// every svg in the folder, by file name. if the puzzle is too small for all of them the
+// first ones are used
+fn load_whimsies(dir: &Path) -> Result<Vec<Whimsy>> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "svg") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            Ok(Whimsy {
+                svg: std::fs::read(path)?.into(),
+            })
+        })
+        .collect()
+}
+
+// queue files are edited from room tasks and http handlers alike
+static QUEUE_FILE_LOCK: Mutex<()> = Mutex::new(());
+
+// the queue.toml of a room using file storage
+pub struct ImageQueue {
     queue_file: PathBuf,
 }
 
 impl ImageQueue {
+    // queue.txt files from before the toml format are converted to a queue.toml next to
+    // them the first time they're used, and left in place afterwards
     pub fn new(queue_file: PathBuf) -> Self {
+        let text_file = queue_file.with_extension("txt");
+        let queue_file = queue_file.with_extension("toml");
+
+        if !queue_file.exists() && text_file.exists() {
+            match Self::migrate(&text_file, &queue_file) {
+                Ok(()) => info!("Migrated {text_file:?} to {queue_file:?}"),
+                Err(e) => warn!("Error migrating {text_file:?}: {e}"),
+            }
+        }
+
         Self { queue_file }
     }
 
-    pub fn top_entry(&self) -> Option<ImageQueueEntry> {
-        let file = File::open(&self.queue_file).unwrap();
-        let reader = BufReader::new(file);
-        for line in reader.lines() {
-            match line {
-                Ok(line) => match ImageQueueEntry::from_line(line.as_str(), false) {
-                    Some(entry) => return Some(entry),
-                    None => continue,
-                },
-                Err(e) => warn!("Error reading line in queue file: {e}"),
+    fn migrate(text_file: &Path, queue_file: &Path) -> Result<()> {
+        let mut queue = QueueFile::default();
+
+        for line in read_to_string(text_file)?.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            // finished entries were commented out, anything else commented is just a comment
+            let (line, status) = match line.strip_prefix('#') {
+                Some(line) => (line, QueueStatus::Done),
+                None => (line, QueueStatus::Queued),
+            };
+
+            match QueueEntry::from_text_line(line) {
+                Ok(entry) => queue.puzzles.push(QueueEntry { status, ..entry }),
+                Err(e) if status == QueueStatus::Queued => {
+                    warn!("Skipping queue line {line:?}: {e}")
+                }
+                Err(_) => (),
             }
         }
 
-        None
+        write_atomically(queue_file, &toml::to_string_pretty(&queue)?)?;
+        Ok(())
     }
 
-    pub fn comment_out_entry(&self, entry: &ImageQueueEntry) {
-        let file = File::open(&self.queue_file).unwrap();
-        let reader = BufReader::new(file);
+    pub fn exists(&self) -> bool {
+        self.queue_file.exists()
+    }
 
-        let mut new_contents = String::new();
-        let mut entry_found = false;
+    pub fn entries(&self) -> Result<Vec<QueueEntry>> {
+        let queue: QueueFile = toml::from_str(&read_to_string(&self.queue_file)?)?;
+        Ok(queue.puzzles)
+    }
 
-        for line in reader.lines().map_while(Result::ok) {
-            if !entry_found {
-                if let Some(line_entry) = ImageQueueEntry::from_line(line.as_str(), false) {
-                    if line_entry == *entry {
-                        entry_found = true;
-                        new_contents.push_str("# ");
-                    }
-                }
+    // writes the entry's status, error and cached image over the stored one, editing just
+    // that table so the rest of the file keeps its comments and formatting
+    pub fn update(&self, index: usize, entry: &QueueEntry) -> Result<()> {
+        let _lock = QUEUE_FILE_LOCK.lock().unwrap();
+        let mut document: Document = read_to_string(&self.queue_file)?.parse()?;
+        let puzzles = document
+            .get_mut("puzzle")
+            .and_then(|puzzles| puzzles.as_array_of_tables_mut())
+            .ok_or_else(|| anyhow!("no [[puzzle]] entries"))?;
+
+        // the file may have been edited since the entry was read, so double check it's
+        // still the same image
+        let image = entry.image.to_string_lossy();
+        let same_image = |table: &Table| {
+            table.get("image").and_then(|image| image.as_str()) == Some(image.as_ref())
+        };
+        let index = if puzzles.get(index).is_some_and(same_image) {
+            index
+        } else {
+            puzzles
+                .iter()
+                .position(same_image)
+                .ok_or_else(|| anyhow!("entry for {image} is gone"))?
+        };
+
+        let table = puzzles.get_mut(index).unwrap();
+        table["status"] = value(entry.status.as_str());
+        match &entry.error {
+            Some(error) => table["error"] = value(error),
+            None => {
+                table.remove("error");
             }
-            new_contents.push_str(line.as_str());
-            new_contents.push('\n');
         }
+        if let Some(cached_image) = &entry.cached_image {
+            table["cached_image"] = value(cached_image.to_string_lossy().as_ref());
+        }
+
+        write_atomically(&self.queue_file, &document.to_string())?;
+        Ok(())
+    }
+
+    pub fn append(&self, entry: QueueEntry) -> Result<usize> {
+        let lock = QUEUE_FILE_LOCK.lock().unwrap();
+
+        let mut document: Document = if self.queue_file.exists() {
+            read_to_string(&self.queue_file)?.parse()?
+        } else {
+            Document::new()
+        };
+
+        // go through a whole queue file so the entry is laid out the same as migrated ones
+        let new_document: Document = toml::to_string_pretty(&QueueFile {
+            puzzles: vec![entry],
+        })?
+        .parse()?;
+        let table = new_document["puzzle"]
+            .as_array_of_tables()
+            .and_then(|puzzles| puzzles.get(0))
+            .ok_or_else(|| anyhow!("couldn't serialize queue entry"))?
+            .clone();
+
+        if !document.contains_key("puzzle") {
+            document["puzzle"] = Item::ArrayOfTables(ArrayOfTables::new());
+        }
+        document["puzzle"]
+            .as_array_of_tables_mut()
+            .ok_or_else(|| anyhow!("puzzle isn't a list of [[puzzle]] entries"))?
+            .push(table);
+
+        write_atomically(&self.queue_file, &document.to_string())?;
+        drop(lock);
 
-        write(&self.queue_file, new_contents).unwrap();
+        Ok(self
+            .entries()?
+            .iter()
+            .filter(|entry| entry.status == QueueStatus::Queued)
+            .count())
     }
 }
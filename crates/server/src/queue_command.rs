@@ -0,0 +1,176 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use clap::{Args, Subcommand};
+use log::info;
+
+use crate::{
+    image_fetcher::ImageFetcher,
+    puzzle_loader::{PuzzleLoader, QueueEntry, QueueStatus},
+    rooms::{Rooms, DEFAULT_ROOM},
+    storage::{open_storage, Storage},
+    Config,
+};
+
+// these go straight to storage, so stop the server first or use the admin api while it runs
+#[derive(Subcommand)]
+pub enum QueueCommand {
+    /// Show every entry in a room's queue and its status
+    List(RoomArg),
+    /// Add an image to the end of a room's queue
+    Add(AddArgs),
+    /// Mark the current puzzle done and drop its backup so the next one starts
+    Skip(RoomArg),
+    /// Check that every queued image can be loaded and cut into a puzzle
+    Validate(RoomArg),
+}
+
+#[derive(Args)]
+pub struct RoomArg {
+    /// Room whose queue to use
+    #[arg(long, default_value = DEFAULT_ROOM)]
+    room: String,
+}
+
+#[derive(Args)]
+pub struct AddArgs {
+    #[command(flatten)]
+    room: RoomArg,
+    /// Image file or http(s) url
+    image: String,
+    /// Target piece count
+    #[arg(long)]
+    pieces: u32,
+    /// Puzzle option in the form key=value, like lock=edges or cut=hexagonal
+    #[arg(long = "option")]
+    options: Vec<String>,
+}
+
+pub fn queue_command(command: QueueCommand, config: Arc<Config>) -> Result<()> {
+    match command {
+        QueueCommand::List(RoomArg { room }) => list(&room, config),
+        QueueCommand::Add(args) => add(args, config),
+        QueueCommand::Skip(RoomArg { room }) => skip(&room, config),
+        QueueCommand::Validate(RoomArg { room }) => validate(&room, config),
+    }
+}
+
+fn entries(storage: &dyn Storage, room: &str) -> Result<Vec<QueueEntry>> {
+    if !storage.has_queue(room) {
+        bail!("room {room} has no queue");
+    }
+    storage.queue_entries(room)
+}
+
+fn list(room: &str, config: Arc<Config>) -> Result<()> {
+    let storage = open_storage(config)?;
+    for (index, entry) in entries(&*storage, room)?.iter().enumerate() {
+        let size = match (entry.pieces, entry.grid) {
+            (Some(pieces), _) => format!("{pieces} pieces"),
+            (None, Some((num_cols, num_rows))) => format!("{num_cols}x{num_rows}"),
+            (None, None) => "no size".to_string(),
+        };
+        let mut line = format!(
+            "{index:>4} {:<8} {size:<12} {}",
+            entry.status.as_str(),
+            entry.image.display()
+        );
+        if let Some(title) = &entry.title {
+            line += &format!(" \"{title}\"");
+        }
+        if let Some(start_time) = &entry.start_time {
+            line += &format!(" starts {start_time}");
+        }
+        if let Some(error) = &entry.error {
+            line += &format!(" ({error})");
+        }
+        println!("{line}");
+    }
+    Ok(())
+}
+
+fn add(args: AddArgs, config: Arc<Config>) -> Result<()> {
+    let room = args.room.room;
+    if !Rooms::is_valid_id(&room) {
+        bail!("invalid room id: {room}");
+    }
+
+    let mut entry = QueueEntry {
+        image: args.image.into(),
+        pieces: Some(args.pieces),
+        ..Default::default()
+    };
+    if ImageFetcher::url(&entry.image).is_none() && !entry.image.is_file() {
+        bail!("no such image: {}", entry.image.display());
+    }
+    for option in &args.options {
+        entry.apply_text_option(option)?;
+    }
+
+    let image = entry.image.clone();
+    let position = open_storage(config)?.enqueue(&room, entry)?;
+    info!("queued {image:?} in room {room} at position {position}");
+    Ok(())
+}
+
+fn skip(room: &str, config: Arc<Config>) -> Result<()> {
+    let storage = open_storage(config.clone())?;
+    let mut puzzle_loader = PuzzleLoader::new(
+        storage.clone(),
+        room.to_string(),
+        ImageFetcher::new(&config),
+    );
+    puzzle_loader.resume();
+
+    let has_backup = storage.load_snapshot(room).is_some();
+    match puzzle_loader.current_entry() {
+        Some(entry) => info!("marking {:?} done", entry.image),
+        None if has_backup => info!("dropping the backup of room {room}"),
+        None => bail!("room {room} has no current puzzle"),
+    }
+
+    // with no backup to resume, the room loads the next queued puzzle when it starts
+    puzzle_loader.pop_current();
+    storage.remove_snapshot(room)?;
+    storage.truncate_events(room, u64::MAX)?;
+
+    if !puzzle_loader.has_pending() {
+        info!("the queue for room {room} is empty now");
+    }
+    Ok(())
+}
+
+// urls are downloaded the same way as when the puzzle starts, so they're cached afterwards
+fn validate(room: &str, config: Arc<Config>) -> Result<()> {
+    let storage = open_storage(config.clone())?;
+    let entries = entries(&*storage, room)?;
+    let puzzle_loader = PuzzleLoader::new(storage, room.to_string(), ImageFetcher::new(&config));
+
+    let mut checked = 0;
+    let mut failed = 0;
+    for (index, mut entry) in entries.into_iter().enumerate() {
+        if entry.status != QueueStatus::Queued {
+            continue;
+        }
+        checked += 1;
+        match puzzle_loader.load_puzzle(index, &mut entry) {
+            Ok(puzzle) => println!(
+                "{index:>4} ok     {}x{} {} pieces {}",
+                puzzle.num_cols(),
+                puzzle.num_rows(),
+                puzzle.piece_count(),
+                entry.image.display()
+            ),
+            Err(e) => {
+                failed += 1;
+                println!("{index:>4} failed {} ({e})", entry.image.display());
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("{failed} of {checked} queued puzzles can't be loaded");
+    }
+    info!("all {checked} queued puzzles can be loaded");
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Args;
+use log::info;
+
+use game::{RenderOptions, TableRenderer};
+
+use crate::storage::StoredBackup;
+
+#[derive(Args)]
+#[command(allow_missing_positional = true)]
+pub struct RenderArgs {
+    /// Puzzle backup to draw
+    #[arg(required_unless_present = "room", conflicts_with = "room")]
+    backup: Option<PathBuf>,
+    /// Png file to write
+    output: PathBuf,
+    #[command(flatten)]
+    stored: StoredBackup,
+    /// Largest width or height of the image in pixels
+    #[arg(long, default_value_t = 2048)]
+    size: u32,
+    /// Draw a box around every group of connected pieces
+    #[arg(long)]
+    outlines: bool,
+    /// Tint locked pieces green and unlocked ones red
+    #[arg(long)]
+    lock_colors: bool,
+}
+
+// a picture of the table as it was when the backup was made, for looking at a stuck or
+// broken room without opening it
+pub fn render(args: RenderArgs) -> Result<()> {
+    let puzzle = args.stored.load(args.backup.as_deref())?.puzzle;
+    info!(
+        "rendering {} pieces, {:.1}% complete",
+        puzzle.piece_indices().len(),
+        puzzle.percent_complete()
+    );
+
+    let options = RenderOptions {
+        group_outlines: args.outlines,
+        lock_colors: args.lock_colors,
+    };
+    let renderer = TableRenderer::new(&puzzle, args.size);
+    renderer.render(&puzzle, &options).save(&args.output)?;
+
+    info!(
+        "wrote {}x{} image to {:?}",
+        renderer.width(),
+        renderer.height(),
+        args.output
+    );
+    Ok(())
+}
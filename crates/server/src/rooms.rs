@@ -0,0 +1,828 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
+use log::{info, warn};
+use serde::Serialize;
+use tokio::{
+    select,
+    sync::{
+        broadcast,
+        mpsc::{error::SendError, unbounded_channel, UnboundedReceiver, UnboundedSender},
+        watch, Mutex, RwLock,
+    },
+    task::spawn_blocking,
+    time::sleep,
+};
+use uuid::Uuid;
+
+use game::{AnyGameEvent, Puzzle, PuzzleChangedEvent};
+
+use crate::{
+    image_fetcher::ImageFetcher,
+    metrics::{
+        self, event_type, APPLY_EVENT_SECONDS, BACKUP_SECONDS, CONNECTED_CLIENTS,
+        EVENT_QUEUE_DEPTH, PUZZLE_PERCENT_COMPLETE,
+    },
+    puzzle_loader::PuzzleLoader,
+    server_game_event::ServerGameEvent,
+    storage::{CompletedPuzzle, JournalEntry, Snapshot, Storage},
+    Config,
+};
+
+// room used by clients that connect to plain /client, backed by the top level queue and
+// backup files from the config
+pub const DEFAULT_ROOM: &str = "default";
+
+// least time between journaled cursor moves of one player
+const CURSOR_JOURNAL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct Room {
+    pub id: String,
+    pub puzzle: Arc<RwLock<Puzzle>>,
+    event_input_tx: UnboundedSender<ServerGameEvent>,
+    pub event_output_tx: broadcast::Sender<ServerGameEvent>,
+    clients: RwLock<HashMap<Uuid, ConnectedClient>>,
+    puzzle_loader: Arc<std::sync::Mutex<PuzzleLoader>>,
+    storage: Arc<dyn Storage>,
+    need_backup: AtomicBool,
+    // one at a time, so an older snapshot can't land after a newer one has truncated the
+    // journal
+    backup_lock: Mutex<()>,
+    // accepted events are journaled between backups when backups are on
+    journaling: bool,
+    journal_tx: UnboundedSender<JournalOp>,
+    journal_seq: AtomicU64,
+    completion_recorded: AtomicBool,
+    // set once the room's metric series are removed, see update_metrics
+    metrics_closed: std::sync::RwLock<bool>,
+    // set by an admin closing the room, see Rooms::close
+    close_tx: watch::Sender<bool>,
+}
+
+enum JournalOp {
+    Append(JournalEntry),
+    Truncate(u64),
+    // the snapshot a new puzzle's archive starts from
+    StartArchive(String),
+    Close,
+}
+
+struct ConnectedClient {
+    addr: String,
+    kick_tx: watch::Sender<()>,
+}
+
+#[derive(Serialize)]
+pub struct RoomStatus {
+    pub room: String,
+    pub piece_count: u32,
+    pub percent_complete: f32,
+    pub players: Vec<PlayerStatus>,
+}
+
+#[derive(Serialize)]
+pub struct PlayerStatus {
+    pub id: Uuid,
+    pub addr: String,
+}
+
+impl Room {
+    // events that come from the server itself rather than any client
+    fn send_server_event(&self, game_event: AnyGameEvent) {
+        let _ = self.event_output_tx.send(ServerGameEvent {
+            client_id: Uuid::nil(),
+            game_event,
+        });
+    }
+
+    // hand a client event to the room's event handler
+    pub fn queue_event(
+        &self,
+        server_event: ServerGameEvent,
+    ) -> Result<(), SendError<ServerGameEvent>> {
+        self.event_input_tx.send(server_event)?;
+        self.update_metrics(|| EVENT_QUEUE_DEPTH.with_label_values(&[&self.id]).inc());
+        Ok(())
+    }
+
+    // clients of a closed room can still be on their way out, and updating the room's series
+    // after they were removed would bring them back with stale values
+    fn update_metrics(&self, update: impl FnOnce()) {
+        let closed = self.metrics_closed.read().unwrap();
+        if !*closed {
+            update();
+        }
+    }
+
+    fn remove_metrics(&self) {
+        let mut closed = self.metrics_closed.write().unwrap();
+        *closed = true;
+        metrics::remove_room(&self.id);
+    }
+
+    pub async fn backup(&self) -> Result<()> {
+        let _backup_lock = self.backup_lock.lock().await;
+        let _timer = BACKUP_SECONDS.with_label_values(&[&self.id]).start_timer();
+        let puzzle = self.puzzle.read().await;
+        let journal_seq = self.journal_seq.load(Ordering::Relaxed);
+        let json = Snapshot::to_json(&puzzle, journal_seq);
+        drop(puzzle);
+        self.write_snapshot(json, journal_seq).await
+    }
+
+    // callers hold the backup lock
+    async fn write_snapshot(&self, json: String, journal_seq: u64) -> Result<()> {
+        let storage = self.storage.clone();
+        let room_id = self.id.clone();
+        spawn_blocking(move || storage.save_snapshot(&room_id, &json))
+            .await
+            .unwrap()?;
+
+        // everything up to here is in the snapshot now
+        let _ = self.journal_tx.send(JournalOp::Truncate(journal_seq));
+        Ok(())
+    }
+
+    // move everyone in the room over to the next puzzle in the queue, false if there isn't one
+    pub async fn next_puzzle(&self) -> bool {
+        let puzzle_loader = self.puzzle_loader.clone();
+        let next_puzzle = spawn_blocking(move || puzzle_loader.lock().unwrap().next())
+            .await
+            .unwrap();
+
+        let Some(next_puzzle) = next_puzzle else {
+            return false;
+        };
+
+        // sent under the write lock so clients that snapshot after the swap don't also get
+        // the change event
+        let backup_lock = self.backup_lock.lock().await;
+        let mut puzzle = self.puzzle.write().await;
+        *puzzle = next_puzzle;
+        self.send_server_event(AnyGameEvent::PuzzleChanged(PuzzleChangedEvent));
+
+        // saved before any events for the new puzzle can be journaled, otherwise a crash
+        // could replay them onto the old one
+        if self.journaling {
+            let journal_seq = self.journal_seq.load(Ordering::Relaxed);
+            let json = Snapshot::to_json(&puzzle, journal_seq);
+            match self.write_snapshot(json.clone(), journal_seq).await {
+                Ok(()) => {
+                    let _ = self.journal_tx.send(JournalOp::StartArchive(json));
+                }
+                Err(e) => warn!("couldn't back up room {}: {e}", self.id),
+            }
+        }
+        drop(puzzle);
+        drop(backup_lock);
+        self.need_backup.store(true, Ordering::Relaxed);
+        self.completion_recorded.store(false, Ordering::Relaxed);
+
+        info!("room {} moved on to the next puzzle", self.id);
+        true
+    }
+
+    // queued puzzles that may just not be due to start yet
+    async fn has_pending_puzzles(&self) -> bool {
+        let puzzle_loader = self.puzzle_loader.clone();
+        spawn_blocking(move || puzzle_loader.lock().unwrap().has_pending())
+            .await
+            .unwrap()
+    }
+
+    // once per puzzle, however long it sits there finished
+    async fn record_completion(&self) {
+        if self.completion_recorded.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let puzzle = self.puzzle.read().await;
+        let title = puzzle.options().title.clone();
+        let piece_count = puzzle.piece_count();
+        drop(puzzle);
+        let players = self.clients.read().await.len();
+
+        let room_id = self.id.clone();
+        let puzzle_loader = self.puzzle_loader.clone();
+        let storage = self.storage.clone();
+        let res = spawn_blocking(move || {
+            let image = puzzle_loader
+                .lock()
+                .unwrap()
+                .current_entry()
+                .map(|entry| entry.image.clone());
+            storage.record_completion(&CompletedPuzzle {
+                room: room_id,
+                image,
+                title,
+                piece_count,
+                players,
+                completed_at: Utc::now(),
+            })
+        })
+        .await
+        .unwrap();
+        if let Err(e) = res {
+            warn!("couldn't record completed puzzle for room {}: {e}", self.id);
+        }
+    }
+
+    pub async fn scatter(&self) {
+        let mut puzzle = self.puzzle.write().await;
+        for event in puzzle.scatter() {
+            self.send_server_event(AnyGameEvent::PieceMoved(event));
+        }
+        drop(puzzle);
+        self.need_backup.store(true, Ordering::Relaxed);
+
+        // the scatter doesn't go through the journal
+        if self.journaling {
+            if let Err(e) = self.backup().await {
+                warn!("couldn't back up room {}: {e}", self.id);
+            }
+        }
+    }
+
+    // the receiver changes when the client should be disconnected
+    pub async fn add_client(&self, client_id: Uuid, addr: String) -> watch::Receiver<()> {
+        let (kick_tx, kick_rx) = watch::channel(());
+        self.clients
+            .write()
+            .await
+            .insert(client_id, ConnectedClient { addr, kick_tx });
+        self.update_metrics(|| CONNECTED_CLIENTS.with_label_values(&[&self.id]).inc());
+        kick_rx
+    }
+
+    pub async fn remove_client(&self, client_id: &Uuid) {
+        if self.clients.write().await.remove(client_id).is_some() {
+            self.update_metrics(|| CONNECTED_CLIENTS.with_label_values(&[&self.id]).dec());
+        }
+    }
+
+    pub async fn kick(&self, client_id: &Uuid) -> bool {
+        match self.clients.read().await.get(client_id) {
+            Some(client) => {
+                let _ = client.kick_tx.send(());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub async fn kick_addr(&self, addr: &str) {
+        for client in self.clients.read().await.values() {
+            if client.addr == addr {
+                let _ = client.kick_tx.send(());
+            }
+        }
+    }
+
+    async fn kick_all(&self) {
+        for client in self.clients.read().await.values() {
+            let _ = client.kick_tx.send(());
+        }
+    }
+
+    pub async fn status(&self) -> RoomStatus {
+        let puzzle = self.puzzle.read().await;
+        let players = self
+            .clients
+            .read()
+            .await
+            .iter()
+            .map(|(id, client)| PlayerStatus {
+                id: *id,
+                addr: client.addr.clone(),
+            })
+            .collect();
+
+        RoomStatus {
+            room: self.id.clone(),
+            piece_count: puzzle.piece_count(),
+            percent_complete: puzzle.percent_complete(),
+            players,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Rooms {
+    config: Arc<Config>,
+    rooms: Arc<Mutex<HashMap<String, Arc<Room>>>>,
+    // held while a room starts up so two clients can't start it twice, without holding up
+    // every other room while its puzzle loads
+    loading: Arc<Mutex<HashMap<String, Arc<Mutex<()>>>>>,
+    storage: Arc<dyn Storage>,
+    image_fetcher: ImageFetcher,
+}
+
+impl Rooms {
+    pub fn new(config: Arc<Config>, storage: Arc<dyn Storage>) -> Self {
+        Self {
+            image_fetcher: ImageFetcher::new(&config),
+            config,
+            rooms: Default::default(),
+            loading: Default::default(),
+            storage,
+        }
+    }
+
+    // letters, digits, dashes and underscores only since the id is also a directory name
+    pub fn is_valid_id(id: &str) -> bool {
+        !id.is_empty()
+            && id.len() <= 64
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    // whether puzzles can be queued for a room, running or not
+    pub fn exists(&self, id: &str) -> bool {
+        Self::is_valid_id(id) && self.storage.room_exists(id)
+    }
+
+    pub fn storage(&self) -> Arc<dyn Storage> {
+        self.storage.clone()
+    }
+
+    // get a running room, or start it up from the given puzzle, its backup or its queue
+    pub async fn open(&self, id: &str, puzzle: Option<Puzzle>) -> Result<Arc<Room>> {
+        if !Self::is_valid_id(id) {
+            bail!("invalid room id: {id}");
+        }
+
+        if let Some(room) = self.get(id).await {
+            return Ok(room);
+        }
+
+        let loading = self
+            .loading
+            .lock()
+            .await
+            .entry(id.to_string())
+            .or_default()
+            .clone();
+        let _loading = loading.lock().await;
+        // whoever held the lock before may have started it already
+        if let Some(room) = self.get(id).await {
+            return Ok(room);
+        }
+
+        let res = self.load(id, puzzle).await;
+        self.loading.lock().await.remove(id);
+        res
+    }
+
+    async fn load(&self, id: &str, puzzle: Option<Puzzle>) -> Result<Arc<Room>> {
+        let room_id = id.to_string();
+        let storage = self.storage.clone();
+        let image_fetcher = self.image_fetcher.clone();
+        let journaling = self.config.backup_puzzle;
+        let (puzzle, puzzle_loader, journal_seq) = spawn_blocking(move || -> Result<_> {
+            let mut puzzle_loader =
+                PuzzleLoader::new(storage.clone(), room_id.clone(), image_fetcher);
+
+            let snapshot = match puzzle {
+                Some(_) => None,
+                None => storage.load_snapshot(&room_id),
+            };
+            let (puzzle, journal_seq) = if let Some(snapshot) = snapshot {
+                info!("resuming room {room_id} from its backup");
+                puzzle_loader.resume();
+                let mut puzzle = snapshot.puzzle;
+                let journal_seq =
+                    replay_journal(&*storage, &room_id, &mut puzzle, snapshot.journal_seq)?;
+                (puzzle, journal_seq)
+            } else {
+                let puzzle = if let Some(puzzle) = puzzle {
+                    puzzle_loader.resume();
+                    puzzle
+                } else if puzzle_loader.has_queue() {
+                    puzzle_loader
+                        .next()
+                        .ok_or_else(|| anyhow!("image queue for room {room_id} is empty"))?
+                } else {
+                    bail!("no such room: {room_id}");
+                };
+
+                // anything journaled belonged to some other puzzle
+                storage.truncate_events(&room_id, u64::MAX)?;
+                if journaling {
+                    storage.start_archive(&room_id, &Snapshot::to_json(&puzzle, 0))?;
+                }
+                (puzzle, 0)
+            };
+            Ok((puzzle, puzzle_loader, journal_seq))
+        })
+        .await??;
+
+        let (event_input_tx, event_input_rx) = unbounded_channel();
+        let (journal_tx, journal_rx) = unbounded_channel();
+        let (event_output_tx, _) = broadcast::channel(self.config.broadcast_channel_size);
+        let (close_tx, close_rx) = watch::channel(false);
+
+        let room = Arc::new(Room {
+            id: id.to_string(),
+            puzzle: Arc::new(RwLock::new(puzzle)),
+            event_input_tx,
+            event_output_tx,
+            clients: Default::default(),
+            puzzle_loader: Arc::new(std::sync::Mutex::new(puzzle_loader)),
+            storage: self.storage.clone(),
+            need_backup: AtomicBool::new(true),
+            backup_lock: Mutex::new(()),
+            journaling: self.config.backup_puzzle,
+            journal_tx,
+            journal_seq: AtomicU64::new(journal_seq),
+            completion_recorded: AtomicBool::new(false),
+            metrics_closed: Default::default(),
+            close_tx,
+        });
+        self.rooms.lock().await.insert(id.to_string(), room.clone());
+
+        // a fresh snapshot for the journal to start from
+        if room.journaling {
+            if let Err(e) = room.backup().await {
+                warn!("couldn't back up room {id}: {e}");
+            }
+        }
+
+        info!("opened room {id}");
+        tokio::spawn(
+            self.clone()
+                .run(room.clone(), event_input_rx, journal_rx, close_rx),
+        );
+
+        Ok(room)
+    }
+
+    // a room that's already running, without starting it up
+    pub async fn get(&self, id: &str) -> Option<Arc<Room>> {
+        self.rooms.lock().await.get(id).cloned()
+    }
+
+    pub async fn all(&self) -> Vec<Arc<Room>> {
+        let mut rooms: Vec<_> = self.rooms.lock().await.values().cloned().collect();
+        rooms.sort_by(|a, b| a.id.cmp(&b.id));
+        rooms
+    }
+
+    // an empty room, false if there already is one with the id
+    pub async fn create(&self, id: &str) -> Result<bool> {
+        if !Self::is_valid_id(id) {
+            bail!("invalid room id: {id}");
+        }
+        if self.exists(id) {
+            return Ok(false);
+        }
+
+        let storage = self.storage.clone();
+        let room_id = id.to_string();
+        spawn_blocking(move || storage.create_room(&room_id)).await??;
+        info!("created room {id}");
+        Ok(true)
+    }
+
+    // stop the room the same way as when it runs out of puzzles, then remove everything
+    // stored for it so it doesn't start up again
+    pub async fn close(&self, id: &str) -> Result<()> {
+        let rooms = self.rooms.lock().await;
+        if let Some(room) = rooms.get(id).cloned() {
+            drop(rooms);
+            room.close_tx.send_replace(true);
+            // run() drops the receiver once it's done
+            room.close_tx.closed().await;
+            return Ok(());
+        }
+
+        // not running, and it can't start while the lock is held
+        let storage = self.storage.clone();
+        let room_id = id.to_string();
+        spawn_blocking(move || clear_room(&*storage, &room_id, true)).await??;
+        drop(rooms);
+        info!("closed room {id}");
+        Ok(())
+    }
+
+    async fn run(
+        self,
+        room: Arc<Room>,
+        mut event_input_rx: UnboundedReceiver<ServerGameEvent>,
+        mut journal_rx: UnboundedReceiver<JournalOp>,
+        mut close_rx: watch::Receiver<bool>,
+    ) {
+        let config = &self.config;
+
+        // the journal is written from a thread of its own, in the order events were applied
+        let storage = self.storage.clone();
+        let room_id = room.id.clone();
+        let journal_writer = spawn_blocking(move || {
+            let mut open = true;
+            while open {
+                let Some(op) = journal_rx.blocking_recv() else {
+                    break;
+                };
+                // whatever else has queued up in the meantime goes out with it, under one sync
+                let mut ops = vec![op];
+                while let Ok(op) = journal_rx.try_recv() {
+                    ops.push(op);
+                }
+                for op in ops {
+                    let res = match op {
+                        JournalOp::Append(entry) => storage.append_event(&room_id, &entry),
+                        JournalOp::Truncate(through_seq) => {
+                            storage.truncate_events(&room_id, through_seq)
+                        }
+                        JournalOp::StartArchive(snapshot) => {
+                            storage.start_archive(&room_id, &snapshot)
+                        }
+                        JournalOp::Close => {
+                            open = false;
+                            break;
+                        }
+                    };
+                    if let Err(e) = res {
+                        warn!("couldn't update journal for room {room_id}: {e}");
+                    }
+                }
+                if let Err(e) = storage.sync_events(&room_id) {
+                    warn!("couldn't sync journal for room {room_id}: {e}");
+                }
+            }
+        });
+
+        // apply events to the puzzle and dispatch the generated events to clients
+        let event_handler = async {
+            let mut cursors_journaled = HashMap::new();
+            while let Some(server_event) = event_input_rx.recv().await {
+                EVENT_QUEUE_DEPTH.with_label_values(&[&room.id]).dec();
+                let timer = APPLY_EVENT_SECONDS
+                    .with_label_values(&[event_type(&server_event.game_event)])
+                    .start_timer();
+
+                // send while still holding the lock so a client snapshotting the puzzle
+                // gets either the snapshot with the event applied or the event, not both
+                let mut puzzle = room.puzzle.write().await;
+                let res_events = puzzle.apply_event(server_event.game_event.clone());
+                timer.observe_duration();
+                // cursors are only there to be watched back, which doesn't need every move
+                let journaled = match &server_event.game_event {
+                    AnyGameEvent::PlayerCursorMoved(_) => {
+                        let now = Instant::now();
+                        let due = cursors_journaled
+                            .get(&server_event.client_id)
+                            .is_none_or(|last| now - *last >= CURSOR_JOURNAL_INTERVAL);
+                        if due {
+                            cursors_journaled.insert(server_event.client_id, now);
+                        }
+                        due
+                    }
+                    AnyGameEvent::PlayerDisconnected(_) => {
+                        cursors_journaled.remove(&server_event.client_id);
+                        true
+                    }
+                    _ => true,
+                };
+                if room.journaling && journaled && !res_events.is_empty() {
+                    let seq = room.journal_seq.fetch_add(1, Ordering::Relaxed) + 1;
+                    let _ = room.journal_tx.send(JournalOp::Append(JournalEntry {
+                        seq,
+                        time: Utc::now(),
+                        client_id: server_event.client_id,
+                        event: server_event.game_event,
+                    }));
+                }
+                for res_event in res_events {
+                    let _ = room.event_output_tx.send(ServerGameEvent {
+                        client_id: server_event.client_id,
+                        game_event: res_event,
+                    });
+                }
+                drop(puzzle);
+                room.need_backup.store(true, Ordering::Relaxed);
+            }
+        };
+
+        let puzzle_backup = async {
+            loop {
+                sleep(config.puzzle_backup_interval).await;
+
+                if config.backup_puzzle && room.need_backup.swap(false, Ordering::Relaxed) {
+                    if let Err(e) = room.backup().await {
+                        warn!("couldn't back up room {}: {e}", room.id);
+                    }
+                }
+            }
+        };
+
+        // swap in the next puzzle from the queue once the current one is done
+        let completion_handler = async {
+            loop {
+                loop {
+                    let puzzle = room.puzzle.read().await;
+                    PUZZLE_PERCENT_COMPLETE
+                        .with_label_values(&[&room.id])
+                        .set(puzzle.percent_complete().into());
+                    if puzzle.is_complete() {
+                        break;
+                    }
+                    drop(puzzle);
+                    sleep(config.completion_check_interval).await;
+                }
+
+                info!("room {} puzzle complete!", room.id);
+                room.record_completion().await;
+                info!(
+                    "loading next puzzle for room {} in {:?}...",
+                    room.id, config.complete_wait_time
+                );
+                sleep(config.complete_wait_time).await;
+
+                // an admin may have skipped ahead in the meantime
+                if room.puzzle.read().await.is_complete()
+                    && !room.next_puzzle().await
+                    && !room.has_pending_puzzles().await
+                {
+                    break;
+                }
+            }
+        };
+
+        select! {
+            _ = event_handler => (),
+            _ = puzzle_backup => (),
+            _ = completion_handler => (),
+            _ = close_rx.wait_for(|close| *close) => (),
+        };
+        let _ = room.journal_tx.send(JournalOp::Close);
+        journal_writer.await.unwrap();
+
+        // out of puzzles or closed. the finished puzzle's backup and event log have to go while
+        // nobody can open the room, so they aren't resumed the next time someone joins
+        let mut rooms = self.rooms.lock().await;
+        rooms.remove(&room.id);
+        let storage = self.storage.clone();
+        let room_id = room.id.clone();
+        let remove = *close_rx.borrow();
+        let res = spawn_blocking(move || clear_room(&*storage, &room_id, remove))
+            .await
+            .unwrap();
+        if let Err(e) = res {
+            warn!("couldn't remove backup for room {}: {e}", room.id);
+        }
+        room.kick_all().await;
+        room.remove_metrics();
+        info!("closed room {}", room.id);
+        drop(close_rx);
+    }
+}
+
+// the backup and journal of a room that's stopped, and the rest of what's stored for it if
+// it's being removed
+fn clear_room(storage: &dyn Storage, room_id: &str, remove: bool) -> Result<()> {
+    storage.remove_snapshot(room_id)?;
+    storage.truncate_events(room_id, u64::MAX)?;
+    if remove {
+        storage.remove_room(room_id)?;
+    }
+    Ok(())
+}
+
+// apply the events journaled since the snapshot was taken, returning the number of the last
+// one. entries the snapshot already has are left over from a crash before the journal was
+// truncated. a missing entry means the journal doesn't follow on from this snapshot, e.g. when
+// it's an older generation, so nothing from there on is applied
+fn replay_journal(
+    storage: &dyn Storage,
+    room_id: &str,
+    puzzle: &mut Puzzle,
+    journal_seq: u64,
+) -> Result<u64> {
+    let mut last_seq = journal_seq;
+    let mut replayed = 0;
+    let mut discarded = 0;
+    for entry in storage.events(room_id)? {
+        if entry.seq <= journal_seq {
+            continue;
+        }
+        if discarded > 0 || entry.seq != last_seq + 1 {
+            // still counted, so new entries are numbered after the ones left behind and the
+            // next backup truncates them
+            last_seq = last_seq.max(entry.seq);
+            discarded += 1;
+            continue;
+        }
+        puzzle.apply_event(entry.event);
+        last_seq = entry.seq;
+        replayed += 1;
+    }
+    if replayed > 0 {
+        info!("replayed {replayed} journaled events for room {room_id}");
+    }
+    if discarded > 0 {
+        warn!(
+            "discarded {discarded} journaled events for room {room_id} after a gap at {}",
+            journal_seq + replayed + 1
+        );
+    }
+    Ok(last_seq)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::remove_dir_all, path::PathBuf};
+
+    use tokio::time::timeout;
+
+    use game::{Color, Cursor, PlayerCursorMovedEvent};
+
+    use super::*;
+    use crate::{admin::load_recording, file_storage::FileStorage};
+
+    const V0_SNAPSHOT: &str = include_str!("../../game/tests/fixtures/snapshot_v0.json");
+
+    // rooms keeping their files in a directory of their own
+    fn file_rooms(name: &str) -> (Rooms, Arc<dyn Storage>, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("rooms_{name}_{}", std::process::id()));
+        let mut config: Config =
+            toml::from_str(include_str!("../../../server_config.toml")).unwrap();
+        config.puzzle_backup_file = dir.join("puzzle_backup.json");
+        config.queue_file = dir.join("queue.toml");
+        config.rooms_dir = dir.join("rooms");
+        let config = Arc::new(config);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let storage: Arc<dyn Storage> = Arc::new(FileStorage::new(config.clone()));
+        (Rooms::new(config, storage.clone()), storage, dir)
+    }
+
+    #[tokio::test]
+    async fn recording_includes_cursor_moves() {
+        let (rooms, storage, dir) = file_rooms("recording");
+        let puzzle = Puzzle::deserialize(V0_SNAPSHOT).unwrap();
+        let room = rooms.open(DEFAULT_ROOM, Some(puzzle)).await.unwrap();
+
+        let cursor = Cursor {
+            color: Color::WHITE,
+            x: 1.0,
+            y: 2.0,
+            clicked: false,
+        };
+        room.queue_event(ServerGameEvent {
+            client_id: Uuid::new_v4(),
+            game_event: AnyGameEvent::PlayerCursorMoved(PlayerCursorMovedEvent {
+                player_id: None,
+                cursor,
+            }),
+        })
+        .unwrap();
+
+        let recording = timeout(Duration::from_secs(5), async {
+            loop {
+                let recording = load_recording(&*storage, DEFAULT_ROOM).unwrap().unwrap();
+                if !recording.events.is_empty() {
+                    break recording;
+                }
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(matches!(
+            recording.events[0].event,
+            AnyGameEvent::PlayerCursorMoved(_)
+        ));
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn closing_removes_room() {
+        let (rooms, storage, dir) = file_rooms("closing");
+        assert!(rooms.create("closing").await.unwrap());
+        assert!(!rooms.create("closing").await.unwrap());
+
+        let puzzle = Puzzle::deserialize(V0_SNAPSHOT).unwrap();
+        rooms.open("closing", Some(puzzle)).await.unwrap();
+        assert!(storage.load_snapshot("closing").is_some());
+
+        timeout(Duration::from_secs(5), rooms.close("closing"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(rooms.get("closing").await.is_none());
+        assert!(storage.load_snapshot("closing").is_none());
+        assert!(!rooms.exists("closing"));
+
+        remove_dir_all(dir).unwrap();
+    }
+}
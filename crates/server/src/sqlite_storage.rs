@@ -0,0 +1,400 @@
+use std::{path::Path, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::warn;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::{
+    puzzle_loader::{QueueEntry, QueueStatus},
+    rooms::DEFAULT_ROOM,
+    storage::{CompletedPuzzle, JournalEntry, Snapshot, Storage},
+};
+
+const SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+    -- in wal mode a power cut can only lose the last commits, the database stays whole
+    PRAGMA synchronous = NORMAL;
+
+    -- rooms created empty, the others exist through what's stored for them
+    CREATE TABLE IF NOT EXISTS rooms (
+        room TEXT PRIMARY KEY,
+        created_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS snapshots (
+        room TEXT PRIMARY KEY,
+        saved_at TEXT NOT NULL,
+        snapshot TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS events (
+        id INTEGER PRIMARY KEY,
+        room TEXT NOT NULL,
+        seq INTEGER NOT NULL,
+        time TEXT NOT NULL,
+        client_id TEXT NOT NULL,
+        event TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS events_room ON events (room);
+
+    CREATE TABLE IF NOT EXISTS archive_starts (
+        room TEXT PRIMARY KEY,
+        snapshot TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS archived_events (
+        id INTEGER PRIMARY KEY,
+        room TEXT NOT NULL,
+        seq INTEGER NOT NULL,
+        time TEXT NOT NULL,
+        client_id TEXT NOT NULL,
+        event TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS archived_events_room ON archived_events (room);
+
+    CREATE TABLE IF NOT EXISTS queue (
+        id INTEGER PRIMARY KEY,
+        room TEXT NOT NULL,
+        image TEXT NOT NULL,
+        status TEXT NOT NULL,
+        entry TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS queue_room ON queue (room);
+
+    CREATE TABLE IF NOT EXISTS completed (
+        id INTEGER PRIMARY KEY,
+        room TEXT NOT NULL,
+        image TEXT,
+        title TEXT,
+        piece_count INTEGER NOT NULL,
+        players INTEGER NOT NULL,
+        completed_at TEXT NOT NULL
+    );
+";
+
+// queue entries are kept whole as json, with the image and status alongside so they can be
+// queried
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn exists(&self, query: &str, room: &str) -> bool {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row(query, [room], |row| row.get(0))
+            .unwrap_or_else(|e| {
+                warn!("error checking room {room}: {e}");
+                false
+            })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn room_exists(&self, room: &str) -> bool {
+        room == DEFAULT_ROOM
+            || self.exists(
+                "SELECT EXISTS (SELECT 1 FROM rooms WHERE room = ?1)
+                    OR EXISTS (SELECT 1 FROM queue WHERE room = ?1)
+                    OR EXISTS (SELECT 1 FROM snapshots WHERE room = ?1)",
+                room,
+            )
+    }
+
+    fn create_room(&self, room: &str) -> Result<()> {
+        self.connection.lock().unwrap().execute(
+            "INSERT OR IGNORE INTO rooms (room, created_at) VALUES (?1, ?2)",
+            params![room, Utc::now()],
+        )?;
+        Ok(())
+    }
+
+    fn remove_room(&self, room: &str) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.savepoint()?;
+        for table in [
+            "rooms",
+            "snapshots",
+            "events",
+            "archive_starts",
+            "archived_events",
+            "queue",
+            "completed",
+        ] {
+            transaction.execute(&format!("DELETE FROM {table} WHERE room = ?1"), [room])?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn save_snapshot(&self, room: &str, snapshot: &str) -> Result<()> {
+        self.connection.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO snapshots (room, saved_at, snapshot) VALUES (?1, ?2, ?3)",
+            params![room, Utc::now(), snapshot],
+        )?;
+        Ok(())
+    }
+
+    fn load_snapshot(&self, room: &str) -> Option<Snapshot> {
+        let snapshot: Option<String> = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT snapshot FROM snapshots WHERE room = ?1",
+                [room],
+                |row| row.get(0),
+            )
+            .optional()
+            .unwrap_or_else(|e| {
+                warn!("error reading snapshot for room {room}: {e}");
+                None
+            });
+
+        match Snapshot::from_json(&snapshot?) {
+            Ok(snapshot) => Some(snapshot),
+            Err(e) => {
+                warn!("skipping corrupt snapshot for room {room}: {e}");
+                None
+            }
+        }
+    }
+
+    fn remove_snapshot(&self, room: &str) -> Result<()> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM snapshots WHERE room = ?1", [room])?;
+        Ok(())
+    }
+
+    // appends go into a transaction that sync_events commits, so a batch of them costs one
+    // sync. anything else written in the meantime goes with them, and uses savepoints since
+    // transactions don't nest
+    fn append_event(&self, room: &str, entry: &JournalEntry) -> Result<()> {
+        let connection = self.connection.lock().unwrap();
+        if connection.is_autocommit() {
+            connection.execute_batch("BEGIN")?;
+        }
+        connection.execute(
+            "INSERT INTO events (room, seq, time, client_id, event) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                room,
+                entry.seq,
+                entry.time,
+                entry.client_id.to_string(),
+                serde_json::to_string(&entry.event)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn sync_events(&self, _room: &str) -> Result<()> {
+        let connection = self.connection.lock().unwrap();
+        if !connection.is_autocommit() {
+            connection.execute_batch("COMMIT")?;
+        }
+        Ok(())
+    }
+
+    fn events(&self, room: &str) -> Result<Vec<JournalEntry>> {
+        read_events(&self.connection.lock().unwrap(), "events", room)
+    }
+
+    fn truncate_events(&self, room: &str, through_seq: u64) -> Result<()> {
+        // sqlite integers are signed
+        let through_seq = through_seq.min(i64::MAX as u64);
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.savepoint()?;
+        transaction.execute(
+            "INSERT INTO archived_events (room, seq, time, client_id, event)
+                SELECT room, seq, time, client_id, event FROM events
+                WHERE room = ?1 AND seq <= ?2 ORDER BY id",
+            params![room, through_seq],
+        )?;
+        transaction.execute(
+            "DELETE FROM events WHERE room = ?1 AND seq <= ?2",
+            params![room, through_seq],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn start_archive(&self, room: &str, snapshot: &str) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.savepoint()?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO archive_starts (room, snapshot) VALUES (?1, ?2)",
+            params![room, snapshot],
+        )?;
+        transaction.execute("DELETE FROM archived_events WHERE room = ?1", [room])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn archive(&self, room: &str) -> Result<Option<(Snapshot, Vec<JournalEntry>)>> {
+        let connection = self.connection.lock().unwrap();
+        let snapshot: Option<String> = connection
+            .query_row(
+                "SELECT snapshot FROM archive_starts WHERE room = ?1",
+                [room],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(snapshot) = snapshot else {
+            return Ok(None);
+        };
+        let snapshot = Snapshot::from_json(&snapshot)?;
+
+        let mut entries = read_events(&connection, "archived_events", room)?;
+        entries.extend(read_events(&connection, "events", room)?);
+        entries.retain(|entry| entry.seq > snapshot.journal_seq);
+        Ok(Some((snapshot, entries)))
+    }
+
+    fn has_queue(&self, room: &str) -> bool {
+        self.exists("SELECT EXISTS (SELECT 1 FROM queue WHERE room = ?1)", room)
+    }
+
+    fn queue_entries(&self, room: &str) -> Result<Vec<QueueEntry>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT entry FROM queue WHERE room = ?1 ORDER BY id")?;
+        let rows = statement.query_map([room], |row| row.get::<_, String>(0))?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(serde_json::from_str(&row?)?);
+        }
+        Ok(entries)
+    }
+
+    fn enqueue(&self, room: &str, entry: QueueEntry) -> Result<usize> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT INTO queue (room, image, status, entry) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                room,
+                entry.image.to_string_lossy(),
+                entry.status.as_str(),
+                serde_json::to_string(&entry)?,
+            ],
+        )?;
+        let queued: usize = connection.query_row(
+            "SELECT COUNT(*) FROM queue WHERE room = ?1 AND status = ?2",
+            params![room, QueueStatus::Queued.as_str()],
+            |row| row.get(0),
+        )?;
+        Ok(queued)
+    }
+
+    fn update_queue_entry(&self, room: &str, index: usize, entry: &QueueEntry) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.savepoint()?;
+
+        let rows: Vec<(i64, String, String)> = transaction
+            .prepare("SELECT id, image, entry FROM queue WHERE room = ?1 ORDER BY id")?
+            .query_map([room], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_, _>>()?;
+
+        let image = entry.image.to_string_lossy();
+        let same_image = |(_, stored_image, _): &&(i64, String, String)| *stored_image == image;
+        let (id, _, stored_entry) = rows
+            .get(index)
+            .filter(same_image)
+            .or_else(|| rows.iter().find(same_image))
+            .ok_or_else(|| anyhow!("entry for {image} is gone"))?;
+
+        let mut stored_entry: QueueEntry = serde_json::from_str(stored_entry)?;
+        stored_entry.status = entry.status;
+        stored_entry.error = entry.error.clone();
+        if entry.cached_image.is_some() {
+            stored_entry.cached_image = entry.cached_image.clone();
+        }
+        transaction.execute(
+            "UPDATE queue SET status = ?1, entry = ?2 WHERE id = ?3",
+            params![
+                stored_entry.status.as_str(),
+                serde_json::to_string(&stored_entry)?,
+                id,
+            ],
+        )?;
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn record_completion(&self, completed: &CompletedPuzzle) -> Result<()> {
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO completed (room, image, title, piece_count, players, completed_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                completed.room,
+                completed
+                    .image
+                    .as_ref()
+                    .map(|image| image.to_string_lossy().into_owned()),
+                completed.title,
+                completed.piece_count,
+                completed.players,
+                completed.completed_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn completed_puzzles(&self, room: &str) -> Result<Vec<CompletedPuzzle>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT image, title, piece_count, players, completed_at FROM completed
+                WHERE room = ?1 ORDER BY id",
+        )?;
+        let rows = statement.query_map([room], |row| {
+            Ok(CompletedPuzzle {
+                room: room.to_string(),
+                image: row.get::<_, Option<String>>(0)?.map(Into::into),
+                title: row.get(1)?,
+                piece_count: row.get(2)?,
+                players: row.get(3)?,
+                completed_at: row.get(4)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+// the events and archived_events tables have the same columns
+fn read_events(connection: &Connection, table: &str, room: &str) -> Result<Vec<JournalEntry>> {
+    let mut statement = connection.prepare(&format!(
+        "SELECT seq, time, client_id, event FROM {table} WHERE room = ?1 ORDER BY id"
+    ))?;
+    let rows = statement.query_map([room], |row| {
+        Ok((
+            row.get::<_, u64>(0)?,
+            row.get::<_, DateTime<Utc>>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+        ))
+    })?;
+
+    let mut events = Vec::new();
+    for row in rows {
+        let (seq, time, client_id, event) = row?;
+        events.push(JournalEntry {
+            seq,
+            time,
+            client_id: client_id.parse()?,
+            event: serde_json::from_str(&event)?,
+        });
+    }
+    Ok(events)
+}
@@ -0,0 +1,158 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use game::{AnyGameEvent, Puzzle};
+
+use crate::{
+    file_storage::FileStorage, load_config, puzzle_loader::QueueEntry,
+    sqlite_storage::SqliteStorage, Config,
+};
+
+#[derive(Deserialize, ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    // queue.toml, puzzle_backup.json and friends, as laid out in the config
+    File,
+    // everything in one sqlite database at sqlite_file
+    Sqlite,
+}
+
+// an accepted game event, numbered in the order it was applied to the room's puzzle
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JournalEntry {
+    pub seq: u64,
+    pub time: DateTime<Utc>,
+    pub client_id: Uuid,
+    pub event: AnyGameEvent,
+}
+
+// a saved puzzle along with the number of the last journal entry it includes, so only the
+// ones after it are replayed
+pub struct Snapshot {
+    pub puzzle: Puzzle,
+    pub journal_seq: u64,
+}
+
+#[derive(Deserialize)]
+struct JournalPosition {
+    // backups from before the journal don't have one
+    #[serde(default)]
+    journal_seq: u64,
+}
+
+impl Snapshot {
+    // the puzzle's own json with a journal_seq field added, which Puzzle::deserialize skips
+    // over so backups can still be loaded without going through here
+    pub fn to_json(puzzle: &Puzzle, journal_seq: u64) -> String {
+        let json = puzzle.serialize();
+        let fields = json.strip_prefix('{').unwrap();
+        format!("{{\"journal_seq\":{journal_seq},{fields}")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let JournalPosition { journal_seq } = serde_json::from_str(json)?;
+        Ok(Self {
+            puzzle: Puzzle::deserialize(json)?,
+            journal_seq,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CompletedPuzzle {
+    pub room: String,
+    // None for puzzles that didn't come from the queue
+    pub image: Option<PathBuf>,
+    pub title: Option<String>,
+    pub piece_count: u32,
+    pub players: usize,
+    pub completed_at: DateTime<Utc>,
+}
+
+// everything a room keeps between restarts. the methods block, so call them from
+// spawn_blocking
+pub trait Storage: Send + Sync {
+    // rooms other than the default one only exist once created or once something has been
+    // stored for them
+    fn room_exists(&self, room: &str) -> bool;
+    // an empty room, for an admin to queue puzzles in
+    fn create_room(&self, room: &str) -> Result<()>;
+    // everything stored for a room, its queue and completed puzzles included
+    fn remove_room(&self, room: &str) -> Result<()>;
+
+    fn save_snapshot(&self, room: &str, snapshot: &str) -> Result<()>;
+    // the newest snapshot that still loads, if any
+    fn load_snapshot(&self, room: &str) -> Option<Snapshot>;
+    fn remove_snapshot(&self, room: &str) -> Result<()>;
+
+    fn append_event(&self, room: &str, entry: &JournalEntry) -> Result<()>;
+    // make the appended entries durable, called after each batch of them
+    fn sync_events(&self, room: &str) -> Result<()>;
+    fn events(&self, room: &str) -> Result<Vec<JournalEntry>>;
+    // move the entries up to and including the given one out to the archive
+    fn truncate_events(&self, room: &str, through_seq: u64) -> Result<()>;
+    // the journal only covers the time since the last backup, so timelapses read the archive,
+    // which has every entry from the puzzle's first snapshot on. starting one drops the last
+    fn start_archive(&self, room: &str, snapshot: &str) -> Result<()>;
+    fn archive(&self, room: &str) -> Result<Option<(Snapshot, Vec<JournalEntry>)>>;
+
+    fn has_queue(&self, room: &str) -> bool;
+    fn queue_entries(&self, room: &str) -> Result<Vec<QueueEntry>>;
+    // returns how many queued puzzles are ahead of the new entry plus one
+    fn enqueue(&self, room: &str, entry: QueueEntry) -> Result<usize>;
+    // store the status, error and cached image of the entry at the index, which is checked
+    // against the entry's image in case the queue changed in the meantime
+    fn update_queue_entry(&self, room: &str, index: usize, entry: &QueueEntry) -> Result<()>;
+
+    fn record_completion(&self, completed: &CompletedPuzzle) -> Result<()>;
+    fn completed_puzzles(&self, room: &str) -> Result<Vec<CompletedPuzzle>>;
+}
+
+pub fn open_storage(config: Arc<Config>) -> Result<Arc<dyn Storage>> {
+    let backend = config.storage;
+    open_backend(config, backend)
+}
+
+fn open_backend(config: Arc<Config>, backend: StorageBackend) -> Result<Arc<dyn Storage>> {
+    Ok(match backend {
+        StorageBackend::File => Arc::new(FileStorage::new(config)),
+        StorageBackend::Sqlite => Arc::new(SqliteStorage::open(&config.sqlite_file)?),
+    })
+}
+
+// for commands that look at a backup, which can be a room's newest one instead of a file
+#[derive(Args)]
+pub struct StoredBackup {
+    /// Load the newest backup of this room instead of a file
+    #[arg(long)]
+    room: Option<String>,
+    /// Storage to load the room's backup from, the config's by default
+    #[arg(long, value_enum)]
+    storage: Option<StorageBackend>,
+}
+
+impl StoredBackup {
+    pub fn load(&self, file: Option<&Path>) -> Result<Snapshot> {
+        let Some(room) = &self.room else {
+            if self.storage.is_some() {
+                bail!("--storage goes with --room");
+            }
+            let file = file.ok_or_else(|| anyhow!("either a backup file or --room is needed"))?;
+            return Snapshot::from_json(&read_to_string(file)?);
+        };
+        let config = load_config()?;
+        let backend = self.storage.unwrap_or(config.storage);
+        open_backend(config, backend)?
+            .load_snapshot(room)
+            .ok_or_else(|| anyhow!("room {room} has no backup"))
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    fs::{create_dir_all, read_to_string, File},
+    io::BufWriter,
+    path::PathBuf,
+};
+
+use anyhow::{bail, Result};
+use chrono::Duration;
+use clap::Args;
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, RgbaImage,
+};
+use log::info;
+
+use game::{AnyGameEvent, PlacedPiece, RenderOptions, TableRenderer};
+
+use crate::{
+    file_storage::read_json_lines,
+    storage::{JournalEntry, Snapshot},
+};
+
+#[derive(Args)]
+pub struct TimelapseArgs {
+    /// Puzzle backup to start from, like a room's puzzle_start.json
+    snapshot: PathBuf,
+    /// Event journal recorded after the backup, one json entry per line, like a room's
+    /// puzzle_events_archive.jsonl
+    journal: PathBuf,
+    /// A .gif file, or a directory to fill with numbered png frames
+    output: PathBuf,
+    /// Seconds of play between frames
+    #[arg(long, default_value_t = 10.0)]
+    interval: f64,
+    /// Largest width or height of a frame in pixels
+    #[arg(long, default_value_t = 1280)]
+    size: u32,
+    /// Frames per second of the gif
+    #[arg(long, default_value_t = 10)]
+    fps: u32,
+}
+
+enum FrameOutput {
+    Pngs(PathBuf),
+    Gif(GifEncoder<BufWriter<File>>, Delay),
+}
+
+impl FrameOutput {
+    fn new(path: &PathBuf, fps: u32) -> Result<Self> {
+        if path.extension().is_some_and(|extension| extension == "gif") {
+            let mut encoder = GifEncoder::new(BufWriter::new(File::create(path)?));
+            encoder.set_repeat(Repeat::Infinite)?;
+            Ok(Self::Gif(
+                encoder,
+                Delay::from_numer_denom_ms(1000, fps.max(1)),
+            ))
+        } else {
+            create_dir_all(path)?;
+            Ok(Self::Pngs(path.clone()))
+        }
+    }
+
+    fn write(&mut self, frame_number: usize, frame: RgbaImage) -> Result<()> {
+        match self {
+            Self::Pngs(dir) => frame.save(dir.join(format!("frame_{frame_number:05}.png")))?,
+            Self::Gif(encoder, delay) => {
+                encoder.encode_frame(Frame::from_parts(frame, 0, 0, *delay))?
+            }
+        }
+        Ok(())
+    }
+}
+
+// one frame for the snapshot, then one for each interval of play in the journal. stretches
+// where nobody moved a piece are skipped rather than held on screen. the frames are only
+// drawn once the journal has been played through, so the canvas can fit all of them
+pub fn timelapse(args: TimelapseArgs) -> Result<()> {
+    if args.interval <= 0.0 {
+        bail!("interval has to be more than zero");
+    }
+    let interval = Duration::milliseconds((args.interval * 1000.0).max(1.0) as i64);
+
+    let snapshot = Snapshot::from_json(&read_to_string(&args.snapshot)?)?;
+    let mut puzzle = snapshot.puzzle;
+    let entries: Vec<JournalEntry> = read_json_lines(&args.journal)?
+        .into_iter()
+        .filter(|entry: &JournalEntry| entry.seq > snapshot.journal_seq)
+        .collect();
+
+    info!(
+        "rendering {} journal entries from {:?}",
+        entries.len(),
+        args.journal
+    );
+
+    let mut extent = TableRenderer::extent(&puzzle);
+    let mut frames = Vec::new();
+    let mut take_frame = |frame: Vec<PlacedPiece>| {
+        for piece in &frame {
+            extent.0 = extent.0.max(piece.x.abs());
+            extent.1 = extent.1.max(piece.y.abs());
+        }
+        frames.push(frame);
+    };
+    take_frame(TableRenderer::placed_pieces(&puzzle));
+
+    if let Some(first_entry) = entries.first() {
+        let mut frame_end = first_entry.time + interval;
+        let mut changed = false;
+        for entry in entries {
+            if entry.time >= frame_end {
+                if changed {
+                    take_frame(TableRenderer::placed_pieces(&puzzle));
+                    changed = false;
+                }
+                let skipped =
+                    (entry.time - frame_end).num_milliseconds() / interval.num_milliseconds();
+                frame_end += interval * (skipped as i32 + 1);
+            }
+
+            let moves_pieces = !matches!(entry.event, AnyGameEvent::PlayerCursorMoved(_));
+            changed |= !puzzle.apply_event(entry.event).is_empty() && moves_pieces;
+        }
+
+        if changed {
+            take_frame(TableRenderer::placed_pieces(&puzzle));
+        }
+    }
+
+    let renderer = TableRenderer::with_extent(&puzzle, extent, args.size);
+    let mut output = FrameOutput::new(&args.output, args.fps)?;
+    for (frame_number, frame) in frames.iter().enumerate() {
+        output.write(
+            frame_number,
+            renderer.render_pieces(frame, &RenderOptions::default()),
+        )?;
+    }
+
+    info!("wrote {} frames to {:?}", frames.len(), args.output);
+    Ok(())
+}
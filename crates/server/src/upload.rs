@@ -0,0 +1,151 @@
+use std::{
+    convert::Infallible,
+    fs::{create_dir_all, write},
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{BufMut, Bytes};
+use futures_util::TryStreamExt;
+use image::io::Reader as ImageReader;
+use log::info;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::task::spawn_blocking;
+use warp::{
+    http::StatusCode,
+    multipart::{FormData, Part},
+    reply::{json, with_status, Response},
+    Reply,
+};
+
+use crate::{puzzle_loader::QueueEntry, rooms::Rooms, Config};
+
+#[derive(Serialize)]
+struct UploadResponse {
+    image: PathBuf,
+    position: usize,
+}
+
+// multipart form with an "image" file and a "pieces" target count. "title", "attribution"
+// and "start_time" (rfc 3339) are optional, as are any of the old queue.txt options like
+// "cut" or "lock" as fields of their own
+pub async fn upload_handler(
+    room_id: String,
+    form: FormData,
+    rooms: Rooms,
+    config: Arc<Config>,
+) -> Result<Response, Infallible> {
+    if !rooms.exists(&room_id) {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let (image, mut entry) = match read_form(form).await {
+        Ok(upload) => upload,
+        Err(e) => return Ok(with_status(e.to_string(), StatusCode::BAD_REQUEST).into_response()),
+    };
+
+    // make sure the puzzle can actually be built before it takes a spot in the queue
+    let max_dimension = config.max_upload_dimension;
+    let checked_entry = entry.clone();
+    let checked_image = image.clone();
+    let extension = match spawn_blocking(move || -> Result<_> {
+        let extension = check_image(&checked_image, max_dimension)?;
+        checked_entry.build_puzzle(checked_image)?;
+        Ok(extension)
+    })
+    .await
+    .unwrap()
+    {
+        Ok(extension) => extension,
+        Err(e) => return Ok(with_status(e.to_string(), StatusCode::BAD_REQUEST).into_response()),
+    };
+
+    entry.image = stored_image_path(&config.upload_dir, &image, extension);
+    let image_path = entry.image.clone();
+    let upload_dir = config.upload_dir.clone();
+    let storage = rooms.storage();
+    let queue_room_id = room_id.clone();
+    let res = spawn_blocking(move || -> Result<_> {
+        create_dir_all(upload_dir)?;
+        write(&entry.image, image)?;
+        storage.enqueue(&queue_room_id, entry)
+    })
+    .await
+    .unwrap();
+
+    Ok(match res {
+        Ok(position) => {
+            info!("queued upload {image_path:?} in room {room_id} at position {position}");
+            json(&UploadResponse {
+                image: image_path,
+                position,
+            })
+            .into_response()
+        }
+        Err(e) => with_status(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    })
+}
+
+async fn read_form(form: FormData) -> Result<(Bytes, QueueEntry)> {
+    let parts: Vec<Part> = form.try_collect().await?;
+
+    let mut image = None;
+    let mut entry = QueueEntry::default();
+
+    for part in parts {
+        let name = part.name().to_string();
+        let data = part
+            .stream()
+            .try_fold(Vec::new(), |mut buf, data| async move {
+                buf.put(data);
+                Ok(buf)
+            })
+            .await?;
+
+        if name == "image" {
+            image = Some(Bytes::from(data));
+            continue;
+        }
+
+        let value = String::from_utf8(data).map_err(|_| anyhow!("{name} isn't text"))?;
+        let value = value.trim();
+        match name.as_str() {
+            "pieces" => entry.pieces = Some(value.parse()?),
+            "title" => entry.title = Some(value.to_string()),
+            "attribution" => entry.attribution = Some(value.to_string()),
+            "start_time" => entry.start_time = Some(value.parse()?),
+            _ => entry.apply_text_option(&format!("{name}={value}"))?,
+        }
+    }
+
+    let image = image.ok_or_else(|| anyhow!("missing image"))?;
+    if entry.pieces.is_none() {
+        bail!("missing pieces");
+    }
+    Ok((image, entry))
+}
+
+// the file extension to save the image with
+fn check_image(image: &Bytes, max_dimension: u32) -> Result<&'static str> {
+    let reader = ImageReader::new(Cursor::new(image)).with_guessed_format()?;
+    let format = reader
+        .format()
+        .ok_or_else(|| anyhow!("unrecognized image format"))?;
+    let (width, height) = reader.into_dimensions()?;
+    if width.max(height) > max_dimension {
+        bail!("image is {width}x{height}, the limit is {max_dimension} on either side");
+    }
+    Ok(format.extensions_str()[0])
+}
+
+// images are stored under their content hash, so the same image is only ever kept once
+pub fn stored_image_path(upload_dir: &Path, image: &[u8], extension: &str) -> PathBuf {
+    let hash: String = Sha256::digest(image)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    upload_dir.join(format!("{hash}.{extension}"))
+}
@@ -0,0 +1,154 @@
+use std::{
+    fs::{create_dir_all, read, read_dir, remove_file, rename, write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use log::{info, warn};
+use tokio::{task::spawn_blocking, time::sleep};
+
+use game::Puzzle;
+
+use crate::{
+    puzzle_loader::QueueEntry, rooms::DEFAULT_ROOM, storage::Storage, upload::stored_image_path,
+    Config,
+};
+
+const REJECTED_DIR: &str = "rejected";
+
+// images dropped straight into the watch folder get the default piece count, ones in a
+// numbered subfolder like watch/1000/ get that many
+pub async fn watch_folder(watch_dir: PathBuf, storage: Arc<dyn Storage>, config: Arc<Config>) {
+    info!("watching {watch_dir:?} for new images");
+    loop {
+        sleep(config.watch_interval).await;
+
+        let watch_dir = watch_dir.clone();
+        let storage = storage.clone();
+        let config = config.clone();
+        let res = spawn_blocking(move || scan(&watch_dir, &*storage, &config))
+            .await
+            .unwrap();
+        if let Err(e) = res {
+            warn!("error scanning watch folder: {e}");
+        }
+    }
+}
+
+fn scan(watch_dir: &Path, storage: &dyn Storage, config: &Config) -> Result<()> {
+    for entry in read_dir(watch_dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            ingest(
+                &path,
+                config.watch_default_pieces,
+                watch_dir,
+                storage,
+                config,
+            );
+        } else if path.is_dir() {
+            let Some(pieces) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.parse().ok())
+            else {
+                continue;
+            };
+            for entry in read_dir(&path)? {
+                let path = entry?.path();
+                if path.is_file() {
+                    ingest(&path, pieces, watch_dir, storage, config);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn ingest(path: &Path, pieces: u32, watch_dir: &Path, storage: &dyn Storage, config: &Config) {
+    let hidden = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_none_or(|name| name.starts_with('.'));
+    if hidden || !settled(path, config) {
+        return;
+    }
+
+    match try_ingest(path, pieces, storage, config) {
+        Ok(position) => info!("queued {path:?} from the watch folder at position {position}"),
+        Err(e) => {
+            warn!("rejected {path:?} from the watch folder: {e}");
+            if let Err(e) = reject(path, watch_dir, &e.to_string()) {
+                warn!("couldn't move {path:?} to the rejected folder: {e}");
+            }
+        }
+    }
+}
+
+// files modified within the last scan interval may still be getting copied in
+fn settled(path: &Path, config: &Config) -> bool {
+    path.metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age >= config.watch_interval)
+}
+
+fn try_ingest(path: &Path, pieces: u32, storage: &dyn Storage, config: &Config) -> Result<usize> {
+    let bytes = Bytes::from(read(path)?);
+
+    let image = Puzzle::image_from_bytes(&bytes)?;
+    let max_dimension = config.max_upload_dimension;
+    if image.width().max(image.height()) > max_dimension {
+        bail!(
+            "image is {}x{}, the limit is {max_dimension} on either side",
+            image.width(),
+            image.height()
+        );
+    }
+
+    let extension = image::guess_format(&bytes)
+        .ok()
+        .map(|format| format.extensions_str()[0])
+        .or_else(|| path.extension().and_then(|extension| extension.to_str()))
+        .ok_or_else(|| anyhow!("unrecognized image format"))?;
+
+    let stored_path = stored_image_path(&config.upload_dir, &bytes, extension);
+    if stored_path.exists() {
+        bail!("duplicate of {stored_path:?}");
+    }
+
+    create_dir_all(&config.upload_dir)?;
+    write(&stored_path, &bytes)?;
+    let position = storage.enqueue(
+        DEFAULT_ROOM,
+        QueueEntry {
+            image: stored_path,
+            pieces: Some(pieces),
+            ..Default::default()
+        },
+    )?;
+    remove_file(path)?;
+
+    Ok(position)
+}
+
+// moved aside with a <file name>.reason.txt next to it
+fn reject(path: &Path, watch_dir: &Path, reason: &str) -> Result<()> {
+    let rejected_dir = watch_dir.join(REJECTED_DIR);
+    create_dir_all(&rejected_dir)?;
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("no file name"))?
+        .to_string_lossy();
+    rename(path, rejected_dir.join(&*file_name))?;
+    write(
+        rejected_dir.join(format!("{file_name}.reason.txt")),
+        format!("{reason}\n"),
+    )?;
+    Ok(())
+}
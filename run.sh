@@ -3,10 +3,7 @@ cd "${0%/*}"
 
 ./server "$@"
 
+# rooms resume from their own backups on restart
 while true; do
-  if [ -f puzzle_backup.json ]; then
-    ./server puzzle_backup.json
-  else
-    ./server
-  fi
+  ./server
 done